@@ -0,0 +1,109 @@
+//! Measures how long lookups take depending on the number of routes, the fan-out of the tree and
+//! whether case is ignored.
+//! Run with `cargo bench`, it only uses std so the numbers are rough but good enough to compare.
+
+use router::case::CaseSensitivity;
+use router::route::new_route;
+use router::router::{new_router, Router};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const LOOKUPS: u32 = 200_000;
+
+/// Looks up all requests in turn until LOOKUPS lookups are done and returns the time per lookup.
+fn measure(r: &Router<usize>, requests: &[String]) -> Duration {
+    let requests: Vec<_> = requests
+        .iter()
+        .map(|request| new_route(request).unwrap())
+        .collect();
+    let start = Instant::now();
+    for i in 0..LOOKUPS {
+        let request = &requests[i as usize % requests.len()];
+        black_box(r.get(black_box(request)));
+    }
+    start.elapsed() / LOOKUPS
+}
+
+fn build(patterns: &[String], case: CaseSensitivity) -> Router<usize> {
+    let mut r = new_router();
+    r.set_case_sensitivity(case);
+    for (idx, pattern) in patterns.iter().enumerate() {
+        r.add_route(&new_route(pattern).unwrap(), idx).unwrap();
+    }
+    r
+}
+
+/// All routes are siblings, so the fan-out is the number of routes.
+fn flat(count: usize) -> (Vec<String>, Vec<String>) {
+    let patterns = (0..count).map(|i| format!("/resource{}/:id", i)).collect();
+    let requests = (0..count).map(|i| format!("/resource{}/42", i)).collect();
+    (patterns, requests)
+}
+
+/// The routes are spread over a tree where every node has `fan_out` children.
+fn nested(count: usize, fan_out: usize) -> (Vec<String>, Vec<String>) {
+    let path = |mut i: usize| {
+        let mut path = String::new();
+        for _ in 0..3 {
+            path.push_str(&format!("/n{}", i % fan_out));
+            i /= fan_out;
+        }
+        path
+    };
+    let patterns = (0..count).map(|i| format!("{}/:id", path(i))).collect();
+    let requests = (0..count).map(|i| format!("{}/42", path(i))).collect();
+    (patterns, requests)
+}
+
+/// The same requests in upper case, they only match if case is ignored.
+fn upper(requests: Vec<String>) -> Vec<String> {
+    requests
+        .iter()
+        .map(|request| request.to_uppercase())
+        .collect()
+}
+
+fn main() {
+    println!(
+        "{:>8} {:>8} {:>18} {:>12}",
+        "routes", "fan-out", "case", "ns/lookup"
+    );
+    let cases = [
+        CaseSensitivity::Sensitive,
+        CaseSensitivity::AsciiInsensitive,
+        CaseSensitivity::UnicodeInsensitive,
+    ];
+    for case in cases {
+        for count in [10, 100, 1000, 3000] {
+            let (patterns, mut requests) = flat(count);
+            if case != CaseSensitivity::Sensitive {
+                requests = upper(requests);
+            }
+            let time = measure(&build(&patterns, case), &requests);
+            let case = format!("{:?}", case);
+            println!(
+                "{:>8} {:>8} {:>18} {:>12}",
+                count,
+                count,
+                case,
+                time.as_nanos()
+            );
+        }
+        for fan_out in [4, 16, 64] {
+            let count = fan_out * fan_out * fan_out;
+            let (patterns, mut requests) = nested(count, fan_out);
+            if case != CaseSensitivity::Sensitive {
+                requests = upper(requests);
+            }
+            let time = measure(&build(&patterns, case), &requests);
+            let case = format!("{:?}", case);
+            println!(
+                "{:>8} {:>8} {:>18} {:>12}",
+                count,
+                fan_out,
+                case,
+                time.as_nanos()
+            );
+        }
+    }
+}
@@ -1,24 +1,14 @@
 extern crate router;
-use std::collections::HashMap;
+use router::params::Params;
 
 trait Handler {
     type Target;
-    fn handle(
-        &mut self,
-        r: &mut RouterType,
-        params: &HashMap<String, String>,
-        msg: u64,
-    ) -> Self::Target;
+    fn handle(&mut self, r: &mut RouterType, params: &Params, msg: u64) -> Self::Target;
 }
 struct SHandler {}
 impl Handler for SHandler {
     type Target = u32;
-    fn handle(
-        &mut self,
-        _r: &mut RouterType,
-        _params: &HashMap<String, String>,
-        _msg: u64,
-    ) -> Self::Target {
+    fn handle(&mut self, _r: &mut RouterType, _params: &Params, _msg: u64) -> Self::Target {
         10
     }
 }
@@ -26,13 +16,8 @@ impl Handler for SHandler {
 struct ObjKindHandler {}
 impl Handler for ObjKindHandler {
     type Target = u32;
-    fn handle(
-        &mut self,
-        _r: &mut RouterType,
-        params: &HashMap<String, String>,
-        _msg: u64,
-    ) -> Self::Target {
-        match params.get(&":objectkind".to_owned()).unwrap().as_str() {
+    fn handle(&mut self, _r: &mut RouterType, params: &Params, _msg: u64) -> Self::Target {
+        match params.get_str("objectkind").unwrap() {
             "default" => 20,
             "sessions" => 30,
             _ => 100,
@@ -43,19 +28,16 @@ impl Handler for ObjKindHandler {
 struct CollHandler {}
 impl Handler for CollHandler {
     type Target = u32;
-    fn handle(
-        &mut self,
-        r: &mut RouterType,
-        params: &HashMap<String, String>,
-        msg: u64,
-    ) -> Self::Target {
-        let route_string = format!("/blah/service/:objectkind/:objectid/{}", msg);
-        let route_item = router::route::new_route(route_string.as_str()).unwrap();
+    fn handle(&mut self, r: &mut RouterType, params: &Params, msg: u64) -> Self::Target {
+        let route_item: router::route::RouteBuf =
+            format!("/blah/service/:objectkind/:objectid/{}", msg)
+                .parse()
+                .unwrap();
         let ih = std::rc::Rc::new(std::cell::RefCell::new(ItemHandler { id: msg as u32 }));
-        r.add_route(&route_item, ih).unwrap();
+        r.add_route(&route_item.as_route(), ih).unwrap();
 
-        match params.get(&":objectkind".to_owned()).unwrap().as_str() {
-            "collection" => match params.get(&":objectid".to_owned()).unwrap().as_str() {
+        match params.get_str("objectkind").unwrap() {
+            "collection" => match params.get_str("objectid").unwrap() {
                 "default" => match msg {
                     1 => 10,
                     2 => 20,
@@ -73,14 +55,9 @@ struct ItemHandler {
 }
 impl Handler for ItemHandler {
     type Target = u32;
-    fn handle(
-        &mut self,
-        _r: &mut RouterType,
-        params: &HashMap<String, String>,
-        msg: u64,
-    ) -> Self::Target {
-        match params.get(&":objectkind".to_owned()).unwrap().as_str() {
-            "collection" => match params.get(&":objectid".to_owned()).unwrap().as_str() {
+    fn handle(&mut self, _r: &mut RouterType, params: &Params, msg: u64) -> Self::Target {
+        match params.get_str("objectkind").unwrap() {
+            "collection" => match params.get_str("objectid").unwrap() {
                 "default" => match msg {
                     1 => 10 + self.id,
                     2 => 20 + self.id,
@@ -93,7 +70,8 @@ impl Handler for ItemHandler {
     }
 }
 
-type RouterType = router::router::Router<std::rc::Rc<std::cell::RefCell<Handler<Target = u32>>>>;
+type RouterType =
+    router::router::Router<std::rc::Rc<std::cell::RefCell<dyn Handler<Target = u32>>>>;
 
 fn main() {
     let mut r: RouterType = router::router::new_router();
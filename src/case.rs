@@ -0,0 +1,57 @@
+use super::percent;
+use std::cmp::Ordering;
+
+/// How the specific parts of routes are compared with the parts of a route that is looked up.
+/// Parameters and wildcards are not affected, captured values always keep their case.
+///
+/// The variants are ordered from strict to loose, a comparison that succeeds in one mode also
+/// succeeds in all looser modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CaseSensitivity {
+    Sensitive,
+    /// Only ASCII letters are compared case-insensitively, so `/API` matches `/api`.
+    AsciiInsensitive,
+    /// All letters are compared by their case folding, so `/STRASSE` also matches `/straße`.
+    UnicodeInsensitive,
+}
+
+fn fold(s: &str) -> impl Iterator<Item = char> + '_ {
+    //going through upper case first folds characters like 'ß' to "ss" like full case folding
+    s.chars()
+        .flat_map(char::to_uppercase)
+        .flat_map(char::to_lowercase)
+}
+
+/// Orders two parts by their case folding after percent-decoding. Parts that are the same in any
+/// mode compare equal, so sorting by this order keeps them next to each other.
+pub(crate) fn folded_cmp(a: &str, b: &str) -> Ordering {
+    if a.is_ascii() && b.is_ascii() && !a.contains('%') && !b.contains('%') {
+        let a = a.bytes().map(|b| b.to_ascii_lowercase());
+        return a.cmp(b.bytes().map(|b| b.to_ascii_lowercase()));
+    }
+    fold(&percent::decode_or_raw(a)).cmp(fold(&percent::decode_or_raw(b)))
+}
+
+/// Returns the strictest mode in which the two parts are the same (after percent-decoding), or
+/// None if they are not the same in any mode up to loosest.
+pub(crate) fn required_mode(
+    name: &str,
+    segment: &str,
+    loosest: CaseSensitivity,
+) -> Option<CaseSensitivity> {
+    if percent::decoded_eq(name, segment) {
+        return Some(CaseSensitivity::Sensitive);
+    }
+    if loosest == CaseSensitivity::Sensitive {
+        return None;
+    }
+    let name = percent::decode_or_raw(name);
+    let segment = percent::decode_or_raw(segment);
+    if name.eq_ignore_ascii_case(&segment) {
+        return Some(CaseSensitivity::AsciiInsensitive);
+    }
+    if loosest == CaseSensitivity::UnicodeInsensitive && fold(&name).eq(fold(&segment)) {
+        return Some(CaseSensitivity::UnicodeInsensitive);
+    }
+    None
+}
@@ -0,0 +1,382 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A constraint on the values a parameter accepts, written after the parameter name in a route
+/// like `/users/:id<u64>` or `/files/:name<[a-z]+>`.
+///
+/// The constraint is either the name of a primitive type (the value has to parse as that type) or
+/// a pattern that has to match the whole segment. Patterns support a subset of regular
+/// expressions: literal characters, `\` escapes, `.`, character classes like `[a-z0-9_]` or
+/// `[^-]` and the quantifiers `?`, `*`, `+`, `{n}`, `{n,}` and `{n,m}`.
+///
+/// Patterns are matched without backtracking, the time grows linearly with the length of the value
+/// for every pattern, so requests can not make a lookup slow by sending values that almost match.
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    source: String,
+    kind: Kind,
+}
+
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Constraint) -> bool {
+        self.source == other.source
+    }
+}
+
+/// The order of the variants is the order in which parameters with these types are tried: unsigned
+/// before signed integers before floats, and smaller types first because they accept fewer values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Type {
+    U8,
+    U16,
+    U32,
+    U64,
+    Usize,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    Isize,
+    I128,
+    F32,
+    F64,
+    Bool,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Type(Type),
+    Pattern(Vec<Piece>),
+}
+
+#[derive(Debug, Clone)]
+enum Atom {
+    Any,
+    Char(char),
+    Class(bool, Vec<(char, char)>),
+}
+
+#[derive(Debug, Clone)]
+struct Piece {
+    atom: Atom,
+    min: usize,
+    max: Option<usize>,
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn parse_type(name: &str) -> Option<Type> {
+    Some(match name {
+        "u8" => Type::U8,
+        "u16" => Type::U16,
+        "u32" => Type::U32,
+        "u64" => Type::U64,
+        "u128" => Type::U128,
+        "usize" => Type::Usize,
+        "i8" => Type::I8,
+        "i16" => Type::I16,
+        "i32" => Type::I32,
+        "i64" => Type::I64,
+        "i128" => Type::I128,
+        "isize" => Type::Isize,
+        "f32" => Type::F32,
+        "f64" => Type::F64,
+        "bool" => Type::Bool,
+        _ => return None,
+    })
+}
+
+fn parse_escape(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<char, String> {
+    chars
+        .next()
+        .ok_or_else(|| "pattern ends with an unfinished escape".to_owned())
+}
+
+fn parse_class(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Atom, String> {
+    let negated = chars.peek() == Some(&'^');
+    if negated {
+        chars.next();
+    }
+    let mut ranges = Vec::new();
+    loop {
+        let start = match chars.next() {
+            None => return Err("character class is not closed".to_owned()),
+            Some(']') if !ranges.is_empty() => break,
+            Some('\\') => parse_escape(chars)?,
+            Some(c) => c,
+        };
+        if chars.peek() == Some(&'-') {
+            chars.next();
+            match chars.peek() {
+                //a trailing dash is a literal dash
+                Some(']') => {
+                    ranges.push((start, start));
+                    ranges.push(('-', '-'));
+                    continue;
+                }
+                _ => {
+                    let end = match chars.next() {
+                        None => return Err("character class is not closed".to_owned()),
+                        Some('\\') => parse_escape(chars)?,
+                        Some(c) => c,
+                    };
+                    if end < start {
+                        return Err(format!(
+                            "invalid range in character class: {}-{}",
+                            start, end
+                        ));
+                    }
+                    ranges.push((start, end));
+                }
+            }
+        } else {
+            ranges.push((start, start));
+        }
+    }
+    Ok(Atom::Class(negated, ranges))
+}
+
+fn parse_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<usize> {
+    let mut number = String::new();
+    while let Some(c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        number.push(*c);
+        chars.next();
+    }
+    number.parse().ok()
+}
+
+fn parse_repetition(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> Result<(usize, Option<usize>), String> {
+    let invalid = || "invalid repetition, expected {n}, {n,} or {n,m}".to_owned();
+    let min = parse_number(chars).ok_or_else(invalid)?;
+    match chars.next() {
+        Some('}') => Ok((min, Some(min))),
+        Some(',') => {
+            let max = parse_number(chars);
+            if chars.next() != Some('}') {
+                return Err(invalid());
+            }
+            match max {
+                Some(max) if max < min => Err(invalid()),
+                max => Ok((min, max)),
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_pattern(source: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let atom = match c {
+            '.' => Atom::Any,
+            '[' => parse_class(&mut chars)?,
+            '\\' => Atom::Char(parse_escape(&mut chars)?),
+            '?' | '*' | '+' | '{' => {
+                return Err(format!("quantifier '{}' without anything to repeat", c))
+            }
+            '(' | ')' | '|' | '^' | '$' | ']' | '}' => {
+                return Err(format!(
+                    "'{}' is not supported in patterns, escape it with '\\'",
+                    c
+                ))
+            }
+            c => Atom::Char(c),
+        };
+        let (min, max) = match chars.peek() {
+            Some('?') => (0, Some(1)),
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('{') => {
+                chars.next();
+                let repetition = parse_repetition(&mut chars)?;
+                pieces.push(Piece {
+                    atom,
+                    min: repetition.0,
+                    max: repetition.1,
+                });
+                continue;
+            }
+            _ => (1, Some(1)),
+        };
+        if min != 1 || max != Some(1) {
+            chars.next();
+        }
+        pieces.push(Piece { atom, min, max });
+    }
+    Ok(pieces)
+}
+
+impl Constraint {
+    /// Parses the text between the angle brackets of a parameter.
+    pub fn new(source: &str) -> Result<Constraint, String> {
+        if source.is_empty() {
+            return Err("empty constraint".to_owned());
+        }
+        let kind = match parse_type(source) {
+            Some(t) => Kind::Type(t),
+            None => Kind::Pattern(parse_pattern(source)?),
+        };
+        Ok(Constraint {
+            source: source.to_owned(),
+            kind,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// The order in which parameters with different constraints are tried at the same position.
+    /// Types come before patterns, since a value that parses as a number or bool is usually meant
+    /// for the typed route, and types are ordered as described for Type. Patterns are ordered by
+    /// their source text, an arbitrary order that only keeps lookups from depending on the order
+    /// in which routes have been added.
+    pub(crate) fn precedence_cmp(&self, other: &Constraint) -> Ordering {
+        match (&self.kind, &other.kind) {
+            (Kind::Type(a), Kind::Type(b)) => a.cmp(b),
+            (Kind::Type(_), Kind::Pattern(_)) => Ordering::Less,
+            (Kind::Pattern(_), Kind::Type(_)) => Ordering::Greater,
+            (Kind::Pattern(_), Kind::Pattern(_)) => self.source.cmp(&other.source),
+        }
+    }
+
+    /// Checks whether the whole value satisfies this constraint.
+    pub fn matches(&self, value: &str) -> bool {
+        match &self.kind {
+            Kind::Type(t) => match t {
+                Type::U8 => value.parse::<u8>().is_ok(),
+                Type::U16 => value.parse::<u16>().is_ok(),
+                Type::U32 => value.parse::<u32>().is_ok(),
+                Type::U64 => value.parse::<u64>().is_ok(),
+                Type::U128 => value.parse::<u128>().is_ok(),
+                Type::Usize => value.parse::<usize>().is_ok(),
+                Type::I8 => value.parse::<i8>().is_ok(),
+                Type::I16 => value.parse::<i16>().is_ok(),
+                Type::I32 => value.parse::<i32>().is_ok(),
+                Type::I64 => value.parse::<i64>().is_ok(),
+                Type::I128 => value.parse::<i128>().is_ok(),
+                Type::Isize => value.parse::<isize>().is_ok(),
+                Type::F32 => value.parse::<f32>().is_ok(),
+                Type::F64 => value.parse::<f64>().is_ok(),
+                Type::Bool => value.parse::<bool>().is_ok(),
+            },
+            Kind::Pattern(pieces) => match_pieces(pieces, value),
+        }
+    }
+}
+
+fn atom_matches(atom: &Atom, c: char) -> bool {
+    match atom {
+        Atom::Any => true,
+        Atom::Char(expected) => *expected == c,
+        Atom::Class(negated, ranges) => {
+            ranges.iter().any(|(start, end)| *start <= c && c <= *end) != *negated
+        }
+    }
+}
+
+/// Values up to this many characters are matched without allocating.
+const INLINE_LEN: usize = 64;
+
+fn match_pieces(pieces: &[Piece], value: &str) -> bool {
+    let len = value.chars().count();
+    if len <= INLINE_LEN {
+        let mut reachable = [false; INLINE_LEN + 1];
+        let mut run = [0; INLINE_LEN + 1];
+        let mut ends = [0; INLINE_LEN + 2];
+        let buffers = (
+            &mut reachable[..=len],
+            &mut run[..=len],
+            &mut ends[..len + 2],
+        );
+        match_positions(pieces, value, buffers)
+    } else {
+        let buffers = (
+            &mut vec![false; len + 1][..],
+            &mut vec![0; len + 1][..],
+            &mut vec![0; len + 2][..],
+        );
+        match_positions(pieces, value, buffers)
+    }
+}
+
+/// Matches the pieces one after another and keeps the set of positions (counted in characters)
+/// in the value where the pieces so far can end. Each piece takes a single pass over the value, so
+/// there is no backtracking and the time only grows linearly with the length of the value,
+/// whatever the pattern looks like.
+fn match_positions(
+    pieces: &[Piece],
+    value: &str,
+    (reachable, run, ends): (&mut [bool], &mut [usize], &mut [isize]),
+) -> bool {
+    let len = reachable.len() - 1;
+    reachable[0] = true;
+    for piece in pieces {
+        //how many characters from each position on the atom matches
+        run[len] = 0;
+        for (idx, c) in value.chars().rev().enumerate() {
+            let pos = len - 1 - idx;
+            run[pos] = if atom_matches(&piece.atom, c) {
+                run[pos + 1] + 1
+            } else {
+                0
+            };
+        }
+        //the piece can end anywhere between taking min and max characters, the ranges of ends
+        //are added up with a difference array
+        ends.iter_mut().for_each(|end| *end = 0);
+        for pos in 0..=len {
+            if !reachable[pos] || run[pos] < piece.min {
+                continue;
+            }
+            let longest = piece.max.map_or(run[pos], |max| max.min(run[pos]));
+            ends[pos + piece.min] += 1;
+            ends[pos + longest + 1] -= 1;
+        }
+        let mut open = 0;
+        let mut any = false;
+        for pos in 0..=len {
+            open += ends[pos];
+            reachable[pos] = open > 0;
+            any |= reachable[pos];
+        }
+        if !any {
+            return false;
+        }
+    }
+    reachable[len]
+}
+
+/// Splits a parameter segment like `:id<u64>` into the name `:id` and the constraint.
+pub(crate) fn split_parameter(segment: &str) -> Result<(&str, Option<Constraint>), String> {
+    match segment.find('<') {
+        None => {
+            if segment.contains('>') {
+                return Err(format!("unexpected '>' in parameter {}", segment));
+            }
+            Ok((segment, None))
+        }
+        Some(start) => {
+            if !segment.ends_with('>') {
+                return Err(format!(
+                    "constraint of parameter {} is not closed with '>'",
+                    segment
+                ));
+            }
+            let name = &segment[..start];
+            let constraint = Constraint::new(&segment[start + 1..segment.len() - 1])?;
+            Ok((name, Some(constraint)))
+        }
+    }
+}
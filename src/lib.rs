@@ -1,5 +1,13 @@
 pub mod router;
 pub mod route;
+pub mod constraint;
+pub mod method;
+pub mod params;
+pub mod case;
+pub mod shared;
+pub mod specificity;
+mod percent;
+mod render;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,233 @@
+use super::params::Params;
+use super::route::Route;
+use super::router::{self, AddRouteError, Collision, CollisionKind, Router};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    Other(String),
+}
+
+impl Method {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Other(name) => name.as_str(),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidMethodError(String);
+
+impl fmt::Display for InvalidMethodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "This is not a valid method name: \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for InvalidMethodError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // Generic error, underlying cause isn't tracked.
+        None
+    }
+}
+
+impl FromStr for Method {
+    type Err = InvalidMethodError;
+
+    /// Method names are case-sensitive like in HTTP, so "get" is an extension method and not GET.
+    fn from_str(s: &str) -> Result<Method, InvalidMethodError> {
+        //methods are tokens as defined in RFC 7230
+        let is_token_char = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+        if s.is_empty() || !s.chars().all(is_token_char) {
+            return Err(InvalidMethodError(s.to_owned()));
+        }
+        Ok(match s {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            other => Method::Other(other.to_owned()),
+        })
+    }
+}
+
+/// The result of routing a request with a method.
+#[derive(Debug, PartialEq)]
+pub enum MethodMatch<I> {
+    /// An item has been added for the path and the method (or for GET if the method was HEAD).
+    Found(I, Params),
+    /// The method was OPTIONS and no item has been added for it. Contains the methods the path allows.
+    Options(Vec<Method>, Params),
+    /// The path exists but not with this method. Contains the methods the path allows.
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+}
+
+/// Formats the allowed methods for an Allow header.
+#[allow(dead_code)]
+pub fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+type Handlers<T> = Vec<(Method, T)>;
+
+/// Routes requests by path and method. Each path can have different items for different methods.
+/// HEAD requests fall back to the GET item and OPTIONS requests are answered with the allowed
+/// methods if no item has been added for them.
+pub struct MethodRouter<T> {
+    router: Router<Handlers<T>>,
+}
+
+#[allow(dead_code)]
+pub fn new_method_router<T>() -> MethodRouter<T> {
+    MethodRouter {
+        router: router::new_router(),
+    }
+}
+
+fn has_method<T>(handlers: &[(Method, T)], method: &Method) -> bool {
+    handlers.iter().any(|(m, _)| m == method)
+}
+
+fn handles<T>(handlers: &[(Method, T)], method: &Method) -> bool {
+    has_method(handlers, method) || (*method == Method::Head && has_method(handlers, &Method::Get))
+}
+
+/// The methods that are allowed for a path, from the handlers of all routes that match it.
+fn allowed_methods<T>(matches: &[&Handlers<T>]) -> Vec<Method> {
+    let mut allowed: Vec<Method> = Vec::new();
+    for (m, _) in matches.iter().flat_map(|handlers| handlers.iter()) {
+        if !allowed.contains(m) {
+            allowed.push(m.clone());
+        }
+    }
+    if allowed.contains(&Method::Get) && !allowed.contains(&Method::Head) {
+        allowed.push(Method::Head);
+    }
+    if !allowed.contains(&Method::Options) {
+        allowed.push(Method::Options);
+    }
+    allowed
+}
+
+fn pick_handler<T>(handlers: &[(Method, T)], method: &Method) -> Option<usize> {
+    handlers
+        .iter()
+        .position(|(m, _)| m == method)
+        .or_else(|| match method {
+            Method::Head => handlers.iter().position(|(m, _)| *m == Method::Get),
+            _ => None,
+        })
+}
+
+impl<T> MethodRouter<T> {
+    /// Adds the item for the method on this route. Different methods can be added to the same
+    /// route, adding the same method twice results in a collision of the kind RouteExists.
+    #[allow(dead_code)]
+    pub fn add(&mut self, method: Method, route: &Route, item: T) -> Result<(), AddRouteError> {
+        if let Some((existing, handlers)) = self.router.item_for_pattern_mut(route) {
+            if has_method(handlers, &method) {
+                return Err(AddRouteError::Collision(Box::new(Collision {
+                    kind: CollisionKind::RouteExists,
+                    route: router::pattern_of(route),
+                    existing,
+                    segment: route.path.len() - 1,
+                })));
+            }
+            handlers.push((method, item));
+            return Ok(());
+        }
+        self.router.add_route(route, vec![(method, item)])
+    }
+
+    /// Removes the item for the method on this route. The route is removed from the router when
+    /// the last method is removed.
+    #[allow(dead_code)]
+    pub fn remove(&mut self, method: &Method, route: &Route) -> Option<T> {
+        let (_, handlers) = self.router.item_for_pattern_mut(route)?;
+        let idx = handlers.iter().position(|(m, _)| m == method)?;
+        let (_, item) = handlers.remove(idx);
+        if handlers.is_empty() {
+            self.router.remove_route(route);
+        }
+        Some(item)
+    }
+
+    #[allow(dead_code)]
+    pub fn route<'a>(&'a mut self, method: &Method, route: &Route) -> MethodMatch<&'a mut T> {
+        let accept = |handlers: &Handlers<T>| handles(handlers, method);
+        //check with a shared borrow first, the mutable one can not be given up again on a miss
+        if self.router.get_filtered(route, &accept).is_none() {
+            return not_handled(self.router.get_all(route), method);
+        }
+        match self.router.route_filtered(route, &accept) {
+            Some((handlers, params)) => match pick_handler(handlers, method) {
+                Some(idx) => MethodMatch::Found(&mut handlers[idx].1, params),
+                None => MethodMatch::NotFound,
+            },
+            None => MethodMatch::NotFound,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get<'a>(&'a self, method: &Method, route: &Route) -> MethodMatch<&'a T> {
+        let accept = |handlers: &Handlers<T>| handles(handlers, method);
+        if let Some((handlers, params)) = self.router.get_filtered(route, &accept) {
+            if let Some(idx) = pick_handler(handlers, method) {
+                return MethodMatch::Found(&handlers[idx].1, params);
+            }
+        }
+        not_handled(self.router.get_all(route), method)
+    }
+}
+
+/// Builds the result for a request where no route has an item for the method. The allowed methods
+/// come from all routes that match the path, the parameters from the most specific one.
+fn not_handled<I, T>(found: Vec<(&Handlers<T>, Params)>, method: &Method) -> MethodMatch<I> {
+    let handlers: Vec<_> = found.iter().map(|(handlers, _)| *handlers).collect();
+    match found.into_iter().next() {
+        None => MethodMatch::NotFound,
+        Some((_, params)) => {
+            if *method == Method::Options {
+                MethodMatch::Options(allowed_methods(&handlers), params)
+            } else {
+                MethodMatch::MethodNotAllowed(allowed_methods(&handlers))
+            }
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use super::percent;
+use super::specificity::{MatchedPart, Specificity};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The parameters that were captured while routing. Names can be given with or without the
+/// leading colon that is used in the route, so `get("id")` and `get(":id")` are the same.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params {
+    map: HashMap<String, String>,
+    query: Query,
+    specificity: Specificity,
+    /// The name of the catch-all and the decoded parts it matched.
+    rest: Option<(String, Vec<String>)>,
+}
+
+/// The pairs of a query string like `a=1&b=2&a=3`. Keys can appear more than once, the pairs keep
+/// the order they had in the query string. A key without '=' has an empty value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+/// Decodes a key or value of a query, if it can not be decoded it is used as it is.
+fn decode(s: &str) -> String {
+    match percent::decode_query(s) {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => s.to_owned(),
+    }
+}
+
+impl Query {
+    /// Parses the pairs and decodes their percent-escapes. '+' is decoded as a space.
+    #[allow(dead_code)]
+    pub fn parse(query: &str) -> Query {
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.find('=') {
+                Some(idx) => (decode(&pair[..idx]), decode(&pair[idx + 1..])),
+                None => (decode(pair), String::new()),
+            })
+            .collect();
+        Query { pairs }
+    }
+
+    /// The first value for the key.
+    #[allow(dead_code)]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All values for the key in the order they appeared in.
+    #[allow(dead_code)]
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[allow(dead_code)]
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// The number of pairs, keys that appear more than once are counted every time.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamError {
+    /// No parameter with this name has been captured.
+    Missing(String),
+    /// The parameter has been captured but could not be parsed. Contains the name, the captured
+    /// value and the error the parser returned.
+    Invalid(String, String, String),
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParamError::Missing(name) => write!(f, "The parameter \"{}\" is missing", name),
+            ParamError::Invalid(name, value, reason) => write!(
+                f,
+                "The parameter \"{}\" has the invalid value \"{}\": {}",
+                name, value, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // Generic error, underlying cause isn't tracked.
+        None
+    }
+}
+
+/// Types that can be built from all parameters of a route at once.
+///
+/// ```
+/// use router::params::{FromParams, ParamError, Params};
+///
+/// struct Item {
+///     kind: String,
+///     id: u64,
+/// }
+///
+/// impl FromParams for Item {
+///     fn from_params(params: &Params) -> Result<Self, ParamError> {
+///         Ok(Item {
+///             kind: params.get("kind")?,
+///             id: params.get("id")?,
+///         })
+///     }
+/// }
+/// ```
+pub trait FromParams: Sized {
+    fn from_params(params: &Params) -> Result<Self, ParamError>;
+}
+
+/// Escapes a decoded part of a catch-all for the joined value, so the joined value can be split
+/// into the same parts again. Parts that decode to `.` or `..` keep their dots escaped, decoding
+/// must not create dot segments that normalization did not see.
+fn escape_rest_part(part: &str) -> Cow<'_, str> {
+    if part == "." || part == ".." {
+        Cow::Owned(part.replace('.', "%2E"))
+    } else if part.contains('%') || part.contains('/') {
+        Cow::Owned(part.replace('%', "%25").replace('/', "%2F"))
+    } else {
+        Cow::Borrowed(part)
+    }
+}
+
+fn strip_colon(name: &str) -> &str {
+    if let Some(stripped) = name.strip_prefix(':') {
+        stripped
+    } else {
+        name
+    }
+}
+
+impl Params {
+    #[allow(dead_code)]
+    pub fn new() -> Params {
+        Params::default()
+    }
+
+    /// Parses the parameter into the requested type.
+    #[allow(dead_code)]
+    pub fn get<T>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let name = strip_colon(name);
+        let value = self
+            .get_str(name)
+            .ok_or_else(|| ParamError::Missing(name.to_owned()))?;
+        value.parse().map_err(|e: T::Err| {
+            ParamError::Invalid(name.to_owned(), value.to_owned(), e.to_string())
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.map.get(strip_colon(name)).map(String::as_str)
+    }
+
+    #[allow(dead_code)]
+    pub fn contains(&self, name: &str) -> bool {
+        self.map.contains_key(strip_colon(name))
+    }
+
+    /// Builds the type from all parameters.
+    #[allow(dead_code)]
+    pub fn parse<P: FromParams>(&self) -> Result<P, ParamError> {
+        P::from_params(self)
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Iterates over the names (without the colon) and values of the parameters of the path.
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.map.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// The pairs of the query string of the route that has been looked up. The parameters of the
+    /// path are not part of it.
+    #[allow(dead_code)]
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// The name of the catch-all at the end of the route and the decoded parts it matched. The
+    /// value under the name joins them with '/', with '%' and '/' inside of the parts escaped
+    /// again so an encoded slash can be told apart from a separator. The parts are fully decoded,
+    /// so check them for `..` before using them as a file path.
+    #[allow(dead_code)]
+    pub fn rest(&self) -> Option<(&str, &[String])> {
+        self.rest
+            .as_ref()
+            .map(|(name, parts)| (name.as_str(), parts.as_slice()))
+    }
+
+    /// How specific the route is that has been found, see Specificity.
+    #[allow(dead_code)]
+    pub fn specificity(&self) -> &Specificity {
+        &self.specificity
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, value: String) {
+        self.map.insert(strip_colon(name).to_owned(), value);
+    }
+
+    pub(crate) fn set_query(&mut self, query: Option<&str>) {
+        self.query = query.map(Query::parse).unwrap_or_default();
+    }
+
+    pub(crate) fn set_rest(&mut self, name: &str, parts: Vec<String>) {
+        let joined: Vec<_> = parts.iter().map(|part| escape_rest_part(part)).collect();
+        self.insert(name, joined.join("/"));
+        self.rest = Some((strip_colon(name).to_owned(), parts));
+    }
+
+    pub(crate) fn matched(&mut self, part: MatchedPart) {
+        self.specificity.push_front(part);
+    }
+}
+
+/// The number of parameters BorrowedParams can hold before it has to allocate.
+const INLINE_PARAMS: usize = 4;
+
+/// The parameters captured by Router::get_borrowed. The names are borrowed from the router (`'t`)
+/// and the values from the route that was looked up (`'r`), so the values are not percent-decoded.
+/// The first INLINE_PARAMS parameters are stored inline, only routes with more parameters allocate.
+///
+/// The parts matched by a catch-all are not joined like in Params, they are returned by rest.
+#[derive(Debug, Clone)]
+pub struct BorrowedParams<'t, 'r> {
+    inline: [(&'t str, &'r str); INLINE_PARAMS],
+    len: usize,
+    overflow: Vec<(&'t str, &'r str)>,
+    rest: Option<(&'t str, &'r [&'r str])>,
+    query: Option<&'r str>,
+}
+
+impl<'t, 'r> BorrowedParams<'t, 'r> {
+    pub(crate) fn new(query: Option<&'r str>) -> BorrowedParams<'t, 'r> {
+        BorrowedParams {
+            inline: [("", ""); INLINE_PARAMS],
+            len: 0,
+            overflow: Vec::new(),
+            rest: None,
+            query,
+        }
+    }
+
+    pub(crate) fn push(&mut self, name: &'t str, value: &'r str) {
+        let name = strip_colon(name);
+        if self.len < INLINE_PARAMS {
+            self.inline[self.len] = (name, value);
+            self.len += 1;
+        } else {
+            self.overflow.push((name, value));
+        }
+    }
+
+    pub(crate) fn set_rest(&mut self, name: &'t str, rest: &'r [&'r str]) {
+        self.rest = Some((name, rest));
+    }
+
+    /// Parses the percent-decoded parameter into the requested type.
+    #[allow(dead_code)]
+    pub fn get<T>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let name = strip_colon(name);
+        let value = self
+            .get_decoded(name)
+            .ok_or_else(|| ParamError::Missing(name.to_owned()))?;
+        value.parse().map_err(|e: T::Err| {
+            ParamError::Invalid(name.to_owned(), value.into_owned(), e.to_string())
+        })
+    }
+
+    /// The value of the parameter as it is in the route.
+    #[allow(dead_code)]
+    pub fn get_str(&self, name: &str) -> Option<&'r str> {
+        let name = strip_colon(name);
+        //routes are matched from the end, so the parameters that come first in the route were
+        //pushed last and win like they do in Params
+        self.iter_pairs()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| *value)
+    }
+
+    /// The value of the parameter with its percent-escapes decoded. This only allocates if the
+    /// value contains escapes.
+    #[allow(dead_code)]
+    pub fn get_decoded(&self, name: &str) -> Option<Cow<'r, str>> {
+        self.get_str(name).map(percent::decode_or_raw)
+    }
+
+    #[allow(dead_code)]
+    pub fn contains(&self, name: &str) -> bool {
+        self.get_str(name).is_some()
+    }
+
+    /// The name and the parts that were matched by a catch-all at the end of the route.
+    #[allow(dead_code)]
+    pub fn rest(&self) -> Option<(&'t str, &'r [&'r str])> {
+        self.rest
+    }
+
+    /// The number of parameters, including the catch-all.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.len + self.overflow.len() + self.rest.iter().count()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the names (without the colon) and values of the parameters, without the
+    /// catch-all.
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = (&'t str, &'r str)> + '_ {
+        self.iter_pairs().copied()
+    }
+
+    /// The query string of the route without the '?', it is not parsed.
+    #[allow(dead_code)]
+    pub fn query(&self) -> Option<&'r str> {
+        self.query
+    }
+
+    fn iter_pairs(&self) -> impl DoubleEndedIterator<Item = &(&'t str, &'r str)> {
+        self.inline[..self.len].iter().chain(self.overflow.iter())
+    }
+}
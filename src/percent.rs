@@ -0,0 +1,108 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+/// Characters that never need to be encoded in a part of a path (the unreserved characters of
+/// RFC 3986).
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_' || b == b'~'
+}
+
+/// Percent-encodes everything but the unreserved characters, so the result can be used as a
+/// single part of a path.
+pub(crate) fn encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if is_unreserved(b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DecodeError {
+    /// A '%' that is not followed by two hex digits, at this byte offset.
+    InvalidEscape(usize),
+    /// The decoded bytes are not valid UTF-8. The offset is where the escape that produced the
+    /// first invalid byte starts.
+    InvalidUtf8(usize),
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes all percent-escapes. Strings without escapes are not copied.
+pub(crate) fn decode(s: &str) -> Result<Cow<'_, str>, DecodeError> {
+    if !s.contains('%') {
+        return Ok(Cow::Borrowed(s));
+    }
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    //where each decoded byte came from, to report errors at the right place
+    let mut origins = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let high = bytes.get(idx + 1).copied().and_then(hex_value);
+            let low = bytes.get(idx + 2).copied().and_then(hex_value);
+            match (high, low) {
+                (Some(high), Some(low)) => decoded.push(high * 16 + low),
+                _ => return Err(DecodeError::InvalidEscape(idx)),
+            }
+            origins.push(idx);
+            idx += 3;
+        } else {
+            decoded.push(bytes[idx]);
+            origins.push(idx);
+            idx += 1;
+        }
+    }
+    String::from_utf8(decoded)
+        .map(Cow::Owned)
+        .map_err(|e| DecodeError::InvalidUtf8(origins[e.utf8_error().valid_up_to()]))
+}
+
+/// Decodes all percent-escapes, if that is not possible the string is used as it is. Routes from
+/// new_route have been checked to be decodable.
+pub(crate) fn decode_or_raw(s: &str) -> Cow<'_, str> {
+    decode(s).unwrap_or(Cow::Borrowed(s))
+}
+
+/// Decodes a key or value of a query string, where '+' stands for a space.
+pub(crate) fn decode_query(s: &str) -> Result<Cow<'_, str>, DecodeError> {
+    if s.contains('+') {
+        let decoded = decode(&s.replace('+', " "))?.into_owned();
+        Ok(Cow::Owned(decoded))
+    } else {
+        decode(s)
+    }
+}
+
+/// Compares two parts of a path by what they are after decoding, so "a%20b" and "a b" are the
+/// same. Parts that can not be decoded are compared as they are.
+pub(crate) fn decoded_eq(a: &str, b: &str) -> bool {
+    if !a.contains('%') && !b.contains('%') {
+        return a == b;
+    }
+    match (decode(a), decode(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Orders two parts of a path by what they are after decoding, the same parts as for decoded_eq
+/// are equal.
+pub(crate) fn decoded_cmp(a: &str, b: &str) -> Ordering {
+    if !a.contains('%') && !b.contains('%') {
+        return a.cmp(b);
+    }
+    decode_or_raw(a).cmp(&decode_or_raw(b))
+}
@@ -0,0 +1,160 @@
+use super::router::{Router, Tree};
+use std::fmt;
+
+/// Writes the item of a leaf, or nothing.
+type Payload<'p, T> = &'p dyn Fn(&T) -> Option<String>;
+
+fn children_of<T>(tree: &Tree<T>) -> Option<&[Tree<T>]> {
+    match tree {
+        Tree::Leaf(..) => None,
+        Tree::Specific(_, children)
+        | Tree::Parameter(_, _, children)
+        | Tree::Wildcard(children) => Some(children),
+    }
+}
+
+/// The text a node is shown with. The empty part in front of the leading slash is shown as `""`.
+fn label<T>(tree: &Tree<T>, payload: Payload<T>) -> String {
+    match tree {
+        Tree::Wildcard(_) => "*".to_owned(),
+        Tree::Specific(name, _) if name.is_empty() => "\"\"".to_owned(),
+        Tree::Specific(name, _) => name.clone(),
+        Tree::Parameter(name, None, _) => name.clone(),
+        Tree::Parameter(name, Some(constraint), _) => format!("{}<{}>", name, constraint),
+        Tree::Leaf(item, catch_all, case_override) => {
+            let mut label = match catch_all {
+                Some(name) => format!("(leaf, catch-all: {})", name),
+                None => "(leaf)".to_owned(),
+            };
+            if let Some(case) = case_override {
+                label.push_str(&format!(" [{:?}]", case));
+            }
+            if let Some(item) = payload(item) {
+                label.push_str(&format!(" = {}", item));
+            }
+            label
+        }
+    }
+}
+
+/// Writes the children below each other, connected with lines like the `tree` command does.
+fn write_children<T>(
+    f: &mut fmt::Formatter,
+    children: &[Tree<T>],
+    indent: &str,
+    payload: Payload<T>,
+) -> fmt::Result {
+    for (idx, c) in children.iter().enumerate() {
+        let last = idx + 1 == children.len();
+        let branch = if last { "└── " } else { "├── " };
+        write!(f, "\n{}{}{}", indent, branch, label(c, payload))?;
+        if let Some(grandchildren) = children_of(c) {
+            let indent = format!("{}{}", indent, if last { "    " } else { "│   " });
+            write_children(f, grandchildren, &indent, payload)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_tree<T>(f: &mut fmt::Formatter, tree: &Tree<T>, payload: Payload<T>) -> fmt::Result {
+    write!(f, "{}", label(tree, payload))?;
+    if let Some(children) = children_of(tree) {
+        write_children(f, children, "", payload)?;
+    }
+    Ok(())
+}
+
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn write_dot_node<T>(
+    dot: &mut String,
+    tree: &Tree<T>,
+    id: &mut usize,
+    payload: Payload<T>,
+) -> usize {
+    let own_id = *id;
+    *id += 1;
+    let shape = match tree {
+        Tree::Leaf(..) => ", shape=box",
+        _ => "",
+    };
+    dot.push_str(&format!(
+        "    n{} [label=\"{}\"{}];\n",
+        own_id,
+        escape_dot(&label(tree, payload)),
+        shape
+    ));
+    for c in children_of(tree).unwrap_or(&[]) {
+        let child_id = write_dot_node(dot, c, id, payload);
+        dot.push_str(&format!("    n{} -> n{};\n", own_id, child_id));
+    }
+    own_id
+}
+
+fn dot<T>(tree: &Tree<T>, payload: Payload<T>) -> String {
+    let mut dot = "digraph router {\n".to_owned();
+    write_dot_node(&mut dot, tree, &mut 0, payload);
+    dot.push_str("}\n");
+    dot
+}
+
+/// Shows the tree indented like the `tree` command, without the items of the leaves.
+impl<T> fmt::Display for Tree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_tree(f, self, &|_| None)
+    }
+}
+
+/// Shows the tree like Display does, with the items of the leaves.
+impl<T: fmt::Debug> fmt::Debug for Tree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_tree(f, self, &|item| Some(format!("{:?}", item)))
+    }
+}
+
+impl<T> fmt::Display for Router<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.tree())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Router<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.tree())
+    }
+}
+
+impl<T> Tree<T> {
+    /// Exports the tree as a graph for Graphviz, without the items of the leaves.
+    #[allow(dead_code)]
+    pub fn to_dot(&self) -> String {
+        dot(self, &|_| None)
+    }
+
+    /// Exports the tree like to_dot, with the items of the leaves.
+    #[allow(dead_code)]
+    pub fn to_dot_with_items(&self) -> String
+    where
+        T: fmt::Debug,
+    {
+        dot(self, &|item| Some(format!("{:?}", item)))
+    }
+}
+
+impl<T> Router<T> {
+    /// Exports the tree of the router as a graph for Graphviz, see Tree::to_dot.
+    #[allow(dead_code)]
+    pub fn to_dot(&self) -> String {
+        self.tree().to_dot()
+    }
+
+    #[allow(dead_code)]
+    pub fn to_dot_with_items(&self) -> String
+    where
+        T: fmt::Debug,
+    {
+        self.tree().to_dot_with_items()
+    }
+}
@@ -1,15 +1,125 @@
+use super::constraint;
+use super::percent::{self, DecodeError};
+use std::borrow::Cow;
+use std::convert::TryFrom;
 use std::fmt;
+use std::str::FromStr;
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Route<'r> {
     pub path: Vec<&'r str>,
+    /// The part after the '?' (without it), if there is one. It is not used for matching.
+    pub query: Option<&'r str>,
+    /// The part after the '#' (without it), if there is one. It is not used for matching.
+    pub fragment: Option<&'r str>,
 }
 
-#[derive(Debug, Clone)]
-pub struct MalformedRouteError(String);
+/// An owned route that does not borrow the string it was parsed from, so it can be stored or
+/// sent to other threads. Use as_route to pass it to the router.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RouteBuf {
+    pub path: Vec<String>,
+    pub query: Option<String>,
+    pub fragment: Option<String>,
+}
+
+/// A route as it has been added to a router, with its parameters and wildcards.
+pub type RoutePattern = RouteBuf;
+
+/// Describes why a route could not be parsed. All variants except Empty carry the byte offset in
+/// the input where the problem was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MalformedRouteError {
+    Empty,
+    MissingLeadingSlash(usize),
+    TrailingSlash(usize),
+    /// Two slashes follow each other.
+    EmptySegment(usize),
+    /// The name after a ':' or '*' is empty or contains characters other than letters, digits
+    /// and '_'.
+    InvalidParameterName(usize),
+    /// A named wildcard like `*rest` is not the last part of the route.
+    WildcardNotLast(usize),
+    /// The constraint of a parameter could not be parsed. Contains the reason.
+    InvalidConstraint(usize, String),
+    /// Two parameters or wildcards of the route have the same name, this is where the second one
+    /// starts.
+    DuplicateParameterName(usize),
+    /// A '%' is not followed by two hex digits.
+    InvalidEscape(usize),
+    /// The percent-escapes do not decode to valid UTF-8.
+    InvalidUtf8(usize),
+}
+
+impl MalformedRouteError {
+    /// The byte offset in the input where the problem was found.
+    #[allow(dead_code)]
+    pub fn offset(&self) -> usize {
+        match self {
+            MalformedRouteError::Empty => 0,
+            MalformedRouteError::MissingLeadingSlash(offset)
+            | MalformedRouteError::TrailingSlash(offset)
+            | MalformedRouteError::EmptySegment(offset)
+            | MalformedRouteError::InvalidParameterName(offset)
+            | MalformedRouteError::WildcardNotLast(offset)
+            | MalformedRouteError::InvalidConstraint(offset, _)
+            | MalformedRouteError::DuplicateParameterName(offset)
+            | MalformedRouteError::InvalidEscape(offset)
+            | MalformedRouteError::InvalidUtf8(offset) => *offset,
+        }
+    }
+}
 
 impl fmt::Display for MalformedRouteError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "This route is malformed: {}", self.0)
+        match self {
+            MalformedRouteError::Empty => write!(f, "This route is malformed: it is empty"),
+            MalformedRouteError::MissingLeadingSlash(offset) => write!(
+                f,
+                "This route is malformed: it has to start with '/' (at byte {})",
+                offset
+            ),
+            MalformedRouteError::TrailingSlash(offset) => write!(
+                f,
+                "This route is malformed: it must not end with '/' (at byte {})",
+                offset
+            ),
+            MalformedRouteError::EmptySegment(offset) => write!(
+                f,
+                "This route is malformed: it contains an empty part (at byte {})",
+                offset
+            ),
+            MalformedRouteError::InvalidParameterName(offset) => write!(
+                f,
+                "This route is malformed: invalid parameter name (at byte {})",
+                offset
+            ),
+            MalformedRouteError::WildcardNotLast(offset) => write!(
+                f,
+                "This route is malformed: a named wildcard has to be the last part (at byte {})",
+                offset
+            ),
+            MalformedRouteError::DuplicateParameterName(offset) => write!(
+                f,
+                "This route is malformed: the parameter name is used twice (at byte {})",
+                offset
+            ),
+            MalformedRouteError::InvalidConstraint(offset, reason) => write!(
+                f,
+                "This route is malformed: invalid constraint (at byte {}): {}",
+                offset, reason
+            ),
+            MalformedRouteError::InvalidEscape(offset) => write!(
+                f,
+                "This route is malformed: '%' has to be followed by two hex digits (at byte {})",
+                offset
+            ),
+            MalformedRouteError::InvalidUtf8(offset) => write!(
+                f,
+                "This route is malformed: the escapes do not decode to valid UTF-8 (at byte {})",
+                offset
+            ),
+        }
     }
 }
 
@@ -20,20 +130,301 @@ impl std::error::Error for MalformedRouteError {
     }
 }
 
-#[allow(dead_code)]
-pub fn new_route(p: &str) -> Result<Route, MalformedRouteError> {
-    //TODO error type
-    if p.len() < 1 {
-        return Err(MalformedRouteError(p.to_owned()));
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Checks a single part of a path, the offset is where the part starts in the whole path.
+fn validate_segment(segment: &str, offset: usize) -> Result<(), MalformedRouteError> {
+    if segment.is_empty() {
+        return Err(MalformedRouteError::EmptySegment(offset));
     }
-    if !p.starts_with("/") {
-        return Err(MalformedRouteError(p.to_owned()));
+    percent::decode(segment).map_err(|e| decode_error(e, offset))?;
+    Ok(())
+}
+
+/// Checks the parameters and wildcards of a part of a route that is added to a router. Paths that
+/// are looked up may contain ':' and '*' anywhere, for them these are ordinary characters.
+fn validate_pattern_segment(
+    segment: &str,
+    offset: usize,
+    last: bool,
+) -> Result<(), MalformedRouteError> {
+    if let Some(name) = segment.strip_prefix('*') {
+        if !name.is_empty() {
+            if !last {
+                return Err(MalformedRouteError::WildcardNotLast(offset));
+            }
+            if !is_valid_name(name) {
+                return Err(MalformedRouteError::InvalidParameterName(offset));
+            }
+        }
+    } else if segment.starts_with(':') {
+        let name_end = segment.find('<').unwrap_or(segment.len());
+        if !is_valid_name(&segment[1..name_end]) {
+            return Err(MalformedRouteError::InvalidParameterName(offset));
+        }
+        if let Err(reason) = constraint::split_parameter(segment) {
+            return Err(MalformedRouteError::InvalidConstraint(
+                offset + name_end,
+                reason,
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn decode_error(error: DecodeError, offset: usize) -> MalformedRouteError {
+    match error {
+        DecodeError::InvalidEscape(idx) => MalformedRouteError::InvalidEscape(offset + idx),
+        DecodeError::InvalidUtf8(idx) => MalformedRouteError::InvalidUtf8(offset + idx),
+    }
+}
+
+/// Checks that the path is well formed without building it.
+pub(crate) fn validate(p: &str) -> Result<(), MalformedRouteError> {
+    if p.is_empty() {
+        return Err(MalformedRouteError::Empty);
+    }
+    if !p.starts_with('/') {
+        return Err(MalformedRouteError::MissingLeadingSlash(0));
+    }
+    if p.ends_with('/') {
+        return Err(MalformedRouteError::TrailingSlash(p.len() - 1));
+    }
+    //skip the empty part in front of the leading slash
+    let mut offset = 1;
+    for segment in p[1..].split('/') {
+        validate_segment(segment, offset)?;
+        offset += segment.len() + 1;
+    }
+    Ok(())
+}
+
+/// Checks the parameters and wildcards of a route that is added to a router. The offsets are
+/// those in the path the route has been parsed from.
+pub(crate) fn validate_pattern(route: &Route) -> Result<(), MalformedRouteError> {
+    validate_pattern_parts(route, true)
+}
+
+/// Checks a prefix that routes are mounted at like validate_pattern. The mounted routes follow
+/// the prefix, so it can not end with a named wildcard.
+pub(crate) fn validate_prefix(route: &Route) -> Result<(), MalformedRouteError> {
+    validate_pattern_parts(route, false)
+}
+
+fn validate_pattern_parts(route: &Route, ends_route: bool) -> Result<(), MalformedRouteError> {
+    let mut names = Vec::new();
+    let mut offset = 0;
+    for (idx, segment) in route.path.iter().enumerate() {
+        let last = ends_route && idx + 1 == route.path.len();
+        validate_pattern_segment(segment, offset, last)?;
+        if let Some(name) = parameter_name(segment) {
+            if names.contains(&name) {
+                return Err(MalformedRouteError::DuplicateParameterName(offset));
+            }
+            names.push(name);
+        }
+        offset += segment.len() + 1;
     }
-    if p.ends_with("/") {
-        //maybe just trim? Not sure
-        return Err(MalformedRouteError(p.to_owned()));
+    Ok(())
+}
+
+/// The name a parameter or named wildcard captures its value under.
+fn parameter_name(segment: &str) -> Option<&str> {
+    if let Some(name) = segment.strip_prefix('*') {
+        Some(name).filter(|name| !name.is_empty())
+    } else if let Some(parameter) = segment.strip_prefix(':') {
+        Some(&parameter[..parameter.find('<').unwrap_or(parameter.len())])
+    } else {
+        None
     }
+}
+
+/// Parses a path, e.g. of a request that is looked up. Only the slashes and the percent-escapes
+/// are checked, so parts like `*.js` or `:x-y` are fine here.
+#[allow(dead_code)]
+pub fn new_route(p: &str) -> Result<Route<'_>, MalformedRouteError> {
+    let (p, query, fragment) = check(p)?;
     Ok(Route {
-        path: p.split("/").collect(),
+        path: p.split('/').collect(),
+        query,
+        fragment,
     })
 }
+
+/// Parses a route that is added to a router. On top of what new_route checks, parameters need
+/// valid names and constraints and a named wildcard has to be the last part. Patterns have no
+/// query or fragment, so `?` and `#` are part of the path, e.g. in a constraint like `<colou?r>`.
+#[allow(dead_code)]
+pub fn new_pattern(p: &str) -> Result<Route<'_>, MalformedRouteError> {
+    validate(p)?;
+    let route = Route {
+        path: p.split('/').collect(),
+        query: None,
+        fragment: None,
+    };
+    validate_pattern(&route)?;
+    Ok(route)
+}
+
+/// Checks the route like new_route without building it. Returns the path, query and fragment.
+pub(crate) fn check(p: &str) -> Result<(&str, Option<&str>, Option<&str>), MalformedRouteError> {
+    let (p, query, fragment) = split_path(p);
+    //the query is not used for matching, Query::parse keeps values with broken escapes as they are
+    validate(p)?;
+    Ok((p, query, fragment))
+}
+
+/// Splits off the query and the fragment. Offsets into the path stay the same because it always
+/// comes first.
+pub(crate) fn split_path(p: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (p, fragment) = match p.find('#') {
+        Some(idx) => (&p[..idx], Some(&p[idx + 1..])),
+        None => (p, None),
+    };
+    let (p, query) = match p.find('?') {
+        Some(idx) => (&p[..idx], Some(&p[idx + 1..])),
+        None => (p, None),
+    };
+    (p, query, fragment)
+}
+
+/// Decodes the escaped dots of a part, they are unreserved and mean the same as a plain '.'.
+fn decode_dots(segment: &str) -> Cow<'_, str> {
+    if segment.contains("%2E") || segment.contains("%2e") {
+        Cow::Owned(segment.replace("%2E", ".").replace("%2e", "."))
+    } else {
+        Cow::Borrowed(segment)
+    }
+}
+
+/// Brings a path into its canonical form: runs of slashes are collapsed, `.` and `..` parts are
+/// resolved as described in RFC 3986 (section 5.2.4) and a trailing slash is removed. Escaped dots
+/// (`%2E`) are decoded first (section 6.2.2.2), so `/a/%2E%2E/b` is resolved like `/a/../b`. A query or
+/// fragment is kept as it is. Paths that do not start with '/' are returned unchanged.
+#[allow(dead_code)]
+pub fn normalize(p: &str) -> String {
+    let (path, query, fragment) = split_path(p);
+    if !path.starts_with('/') {
+        return p.to_owned();
+    }
+    let mut segments: Vec<Cow<str>> = Vec::new();
+    for segment in path.split('/').map(decode_dots) {
+        match segment.as_ref() {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let mut normalized = String::with_capacity(p.len());
+    for segment in &segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    if let Some(query) = query {
+        normalized.push('?');
+        normalized.push_str(query);
+    }
+    if let Some(fragment) = fragment {
+        normalized.push('#');
+        normalized.push_str(fragment);
+    }
+    normalized
+}
+
+impl<'r> Route<'r> {
+    #[allow(dead_code)]
+    pub fn to_route_buf(&self) -> RouteBuf {
+        RouteBuf::from(self)
+    }
+}
+
+impl RouteBuf {
+    #[allow(dead_code)]
+    pub fn as_route(&self) -> Route<'_> {
+        Route::from(self)
+    }
+}
+
+impl<'r> From<&Route<'r>> for RouteBuf {
+    fn from(route: &Route<'r>) -> RouteBuf {
+        RouteBuf {
+            path: route.path.iter().map(|s| (*s).to_owned()).collect(),
+            query: route.query.map(str::to_owned),
+            fragment: route.fragment.map(str::to_owned),
+        }
+    }
+}
+
+impl<'r> From<Route<'r>> for RouteBuf {
+    fn from(route: Route<'r>) -> RouteBuf {
+        RouteBuf::from(&route)
+    }
+}
+
+impl<'r> From<&'r RouteBuf> for Route<'r> {
+    fn from(route: &'r RouteBuf) -> Route<'r> {
+        Route {
+            path: route.path.iter().map(String::as_str).collect(),
+            query: route.query.as_deref(),
+            fragment: route.fragment.as_deref(),
+        }
+    }
+}
+
+impl<'r> TryFrom<&'r str> for Route<'r> {
+    type Error = MalformedRouteError;
+
+    fn try_from(p: &'r str) -> Result<Route<'r>, MalformedRouteError> {
+        new_route(p)
+    }
+}
+
+impl FromStr for RouteBuf {
+    type Err = MalformedRouteError;
+
+    fn from_str(p: &str) -> Result<RouteBuf, MalformedRouteError> {
+        new_route(p).map(RouteBuf::from)
+    }
+}
+
+impl TryFrom<&str> for RouteBuf {
+    type Error = MalformedRouteError;
+
+    fn try_from(p: &str) -> Result<RouteBuf, MalformedRouteError> {
+        p.parse()
+    }
+}
+
+impl TryFrom<String> for RouteBuf {
+    type Error = MalformedRouteError;
+
+    fn try_from(p: String) -> Result<RouteBuf, MalformedRouteError> {
+        p.parse()
+    }
+}
+
+impl<'r> fmt::Display for Route<'r> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.join("/"))?;
+        if let Some(query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RouteBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_route())
+    }
+}
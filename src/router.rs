@@ -1,279 +1,1931 @@
-use super::route::Route;
+use super::case::{self, CaseSensitivity};
+use super::constraint::{self, Constraint};
+use super::params::{BorrowedParams, Params};
+use super::percent;
+use super::route::{self, Route, RouteBuf, RoutePattern};
+use super::specificity::MatchedPart;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
+use std::ops::Range;
 
+#[derive(Clone)]
 pub enum Tree<T> {
     Wildcard(Vec<Tree<T>>),
     Specific(String, Vec<Tree<T>>),
-    Parameter(String, Vec<Tree<T>>),
-    Leaf(T, bool),
+    Parameter(String, Option<Constraint>, Vec<Tree<T>>),
+    /// The item of a route. If the route ends with a wildcard the leaf also matches longer routes
+    /// and the name the rest of the route is captured under is stored here. The last field
+    /// overrides the case sensitivity of the router for this route.
+    Leaf(T, Option<String>, Option<CaseSensitivity>),
 }
 
+#[derive(Clone)]
 pub struct Router<T> {
-    pub tree: Tree<T>,
+    tree: Tree<T>,
+    strict: bool,
+    names: HashMap<String, RouteBuf>,
+    normalization: Normalization,
+    case: CaseSensitivity,
+    /// The loosest case sensitivity of the router and all routes that have been added, lookups
+    /// do not need to compare any looser than this.
+    loosest_case: CaseSensitivity,
+}
+
+/// How route_path and get_path deal with paths that are not in their canonical form (see
+/// route::normalize), e.g. `/a//b/`, `/a/./b` or `/a/c/../b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    /// The path is used as it is, paths that new_route rejects are errors.
+    Strict,
+    /// The canonical form of the path is looked up.
+    IgnoreTrailingSlash,
+    /// If the path is not canonical but its canonical form matches a route, the canonical form is
+    /// returned so the client can be redirected to it.
+    RedirectToCanonical,
+}
+
+/// The result of looking up a path with route_path or get_path.
+#[derive(Debug, PartialEq)]
+pub enum PathMatch<I> {
+    Found(I, Params),
+    /// The canonical form of the path (including the query and fragment) that should be used
+    /// instead.
+    Redirect(String),
+    NotFound,
 }
 
+/// Creates a router that allows specific parts, parameters and wildcards next to each other. When
+/// looking up a route they are tried in that order, so the most specific route that matches wins.
 #[allow(dead_code)]
 pub fn new_router<T>() -> Router<T> {
     Router {
         tree: Tree::Wildcard(Vec::new()),
+        strict: false,
+        names: HashMap::new(),
+        normalization: Normalization::Strict,
+        case: CaseSensitivity::Sensitive,
+        loosest_case: CaseSensitivity::Sensitive,
+    }
+}
+
+/// Creates a router that refuses to add routes where different types of parts would be next to
+/// each other (e.g. /a/b/c and /a/:param/c), so there is never more than one route that matches.
+#[allow(dead_code)]
+pub fn new_strict_router<T>() -> Router<T> {
+    Router {
+        tree: Tree::Wildcard(Vec::new()),
+        strict: true,
+        names: HashMap::new(),
+        normalization: Normalization::Strict,
+        case: CaseSensitivity::Sensitive,
+        loosest_case: CaseSensitivity::Sensitive,
+    }
+}
+
+/// The type of a part of a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartKind {
+    Specific,
+    Parameter,
+    Wildcard,
+}
+
+/// Why a route collides with a route that has been added before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionKind {
+    /// The route has already been added (maybe with different names or case, if they would match
+    /// the same paths).
+    RouteExists,
+    /// A strict router does not allow different types of parts next to each other. Contains the
+    /// type of the existing part and the type of the new one.
+    MismatchTypes(PartKind, PartKind),
+    /// Two parameters with different names would match the same parts.
+    MismatchParameter,
+}
+
+/// A route that could not be added because of a route that has been added before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub kind: CollisionKind,
+    /// The route that could not be added.
+    pub route: RoutePattern,
+    /// A route that is in the way, as it is stored in the tree.
+    pub existing: RoutePattern,
+    /// The index of the part where the routes collide, 0 is the empty part in front of the
+    /// leading slash.
+    pub segment: usize,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.kind {
+            CollisionKind::RouteExists => "the route has already been added".to_owned(),
+            CollisionKind::MismatchTypes(existing, new) => format!(
+                "the existing part is a {:?}, the new one a {:?}",
+                existing, new
+            ),
+            CollisionKind::MismatchParameter => {
+                "two parameters would match the same parts".to_owned()
+            }
+        };
+        write!(
+            f,
+            "{} collides with {} at part {}: {}",
+            self.route, self.existing, self.segment, reason
+        )
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AddRouteError {
-    MismatchTypes(String, String),
-    MismatchParameter(String, String),
-    RouteExists(String),
+    /// The route collides with a route that has been added before.
+    Collision(Box<Collision>),
+    /// The route has no parts at all.
+    EmptyRoute,
+    /// The tree is in a state that add_route would never have produced. Contains a description.
+    CorruptTree(String),
+    /// Another route has already been added with this name.
+    NameExists(String),
+    /// A parameter or wildcard of the route is malformed, see route::new_pattern.
+    MalformedPattern(route::MalformedRouteError),
+    /// A route of a mounted router could not be added. Contains the route with the prefix and
+    /// why it could not be added.
+    MountCollision(String, Box<AddRouteError>),
 }
 
 impl fmt::Display for AddRouteError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            AddRouteError::RouteExists(t1) => write!(
-                f,
-                "This route has already been added: {}",
-                t1,
-            ),
-            AddRouteError::MismatchTypes(t1, t2) => write!(
-                f,
-                "tried to add path so that two different types of parts collide: {} and {}",
-                t1, t2
-            ),
-            AddRouteError::MismatchParameter(t1, t2) => write!(
+            AddRouteError::Collision(collision) => {
+                write!(f, "tried to add a route that {}", collision)
+            }
+            AddRouteError::EmptyRoute => write!(f, "tried to add a route without any parts"),
+            AddRouteError::NameExists(t1) => {
+                write!(f, "A route with this name has already been added: {}", t1)
+            }
+            AddRouteError::CorruptTree(t1) => {
+                write!(f, "the tree of the router is corrupt: {}", t1)
+            }
+            AddRouteError::MalformedPattern(e) => write!(f, "{}", e),
+            AddRouteError::MountCollision(t1, e) => {
+                write!(f, "could not mount the route {}: {}", t1, e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AddRouteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // Generic error, underlying cause isn't tracked.
+        None
+    }
+}
+
+/// The error of mount. It gives back the router that was to be mounted, so its routes are not lost.
+pub struct MountError<T> {
+    pub error: AddRouteError,
+    pub child: Box<Router<T>>,
+}
+
+impl<T> fmt::Debug for MountError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MountError")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for MountError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl<T> std::error::Error for MountError<T> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouterError {
+    /// The tree is in a state that add_route would never have produced. Contains a description.
+    CorruptTree(String),
+}
+
+impl fmt::Display for RouterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RouterError::CorruptTree(t1) => write!(f, "the tree of the router is corrupt: {}", t1),
+        }
+    }
+}
+
+impl std::error::Error for RouterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        // Generic error, underlying cause isn't tracked.
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UrlError {
+    /// No route has been added with this name.
+    UnknownRoute(String),
+    /// The route needs this parameter but it has not been given.
+    MissingParameter(String),
+    /// This parameter has been given but the route does not use it.
+    ExtraParameter(String),
+    /// The value does not fit into the route. Contains the name and the value.
+    InvalidParameter(String, String),
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UrlError::UnknownRoute(t1) => write!(f, "There is no route with the name {}", t1),
+            UrlError::MissingParameter(t1) => write!(f, "The parameter {} is missing", t1),
+            UrlError::ExtraParameter(t1) => {
+                write!(f, "The parameter {} is not used by the route", t1)
+            }
+            UrlError::InvalidParameter(t1, t2) => write!(
                 f,
-                "tried to add path so that a paramter and another part collide: {} and {}",
-                t1, t2
+                "The value \"{}\" can not be used for the parameter {}",
+                t2, t1
             ),
         }
     }
 }
 
-impl std::error::Error for AddRouteError {
+impl std::error::Error for UrlError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         // Generic error, underlying cause isn't tracked.
         None
     }
 }
 
+/// Whether the value can not be a part of a url. `.` and `..` would be resolved as dot segments
+/// (even when escaped) and change which route the url leads to.
+fn is_invalid_part(value: &str) -> bool {
+    value.is_empty() || value == "." || value == ".."
+}
+
+/// Fills the parameters and wildcards of the pattern with the given values.
+fn build_url(pattern: &RouteBuf, params: &[(&str, &str)]) -> Result<String, UrlError> {
+    let value_of = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.strip_prefix(':').unwrap_or(key) == name)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| UrlError::MissingParameter(name.to_owned()))
+    };
+    let mut used = Vec::new();
+    let mut parts = Vec::with_capacity(pattern.path.len());
+    for (idx, segment) in pattern.path.iter().enumerate() {
+        let last = idx == pattern.path.len() - 1;
+        if let Some(name) = segment.strip_prefix('*') {
+            let name = if name.is_empty() { "*" } else { name };
+            let value = value_of(name)?;
+            let invalid = if last {
+                value.split('/').any(is_invalid_part)
+            } else {
+                is_invalid_part(value)
+            };
+            if invalid {
+                return Err(UrlError::InvalidParameter(
+                    name.to_owned(),
+                    value.to_owned(),
+                ));
+            }
+            if last {
+                //the rest of the route keeps its slashes
+                let encoded: Vec<String> = value.split('/').map(percent::encode).collect();
+                parts.push(encoded.join("/"));
+            } else {
+                parts.push(percent::encode(value));
+            }
+            used.push(name);
+        } else if segment.starts_with(':') {
+            let (name, constraint) = parameter_parts(segment);
+            let name = &name[1..];
+            let value = value_of(name)?;
+            let fits = match &constraint {
+                Some(constraint) => constraint.matches(value),
+                None => true,
+            };
+            if is_invalid_part(value) || !fits {
+                return Err(UrlError::InvalidParameter(
+                    name.to_owned(),
+                    value.to_owned(),
+                ));
+            }
+            parts.push(percent::encode(value));
+            used.push(name);
+        } else {
+            parts.push(segment.clone());
+        }
+    }
+    for (key, _) in params {
+        let key = key.strip_prefix(':').unwrap_or(key);
+        if !used.contains(&key) {
+            return Err(UrlError::ExtraParameter(key.to_owned()));
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+/// Checks the children of a node (and all nodes below them) for the invariants that add_route
+/// keeps. Lookups rely on them to never go wrong. `pattern` holds the parts of the tree down to
+/// the children, the routes that end in leaves have to pass the same checks as new_pattern.
+fn check_children<T>(
+    children: &[Tree<T>],
+    below_wildcard: bool,
+    pattern: &mut Vec<String>,
+) -> Result<(), RouterError> {
+    let corrupt = |reason: String| Err(RouterError::CorruptTree(reason));
+    for (idx, c) in children.iter().enumerate() {
+        //two nodes for the same part of a route can not be told apart when adding routes
+        for other in &children[idx + 1..] {
+            let same = match (c, other) {
+                (Tree::Leaf(..), Tree::Leaf(..)) => true,
+                (Tree::Leaf(..), _) | (_, Tree::Leaf(..)) => false,
+                _ => is_node_for_segment(other, &segment_of(c)),
+            };
+            if same {
+                return corrupt(format!("the part {} exists twice", segment_of(c)));
+            }
+        }
+        //the parts are checked the same way as they are when parsing a route
+        let segment = match c {
+            Tree::Leaf(_, catch_all, _) => {
+                if catch_all.is_some() && !below_wildcard {
+                    return corrupt("a catch-all leaf has to be below a wildcard".to_owned());
+                }
+                //a whole route, so names used twice are found too
+                let leaf = leaf_pattern(pattern, catch_all);
+                let route = Route {
+                    path: leaf.path.iter().map(String::as_str).collect(),
+                    query: None,
+                    fragment: None,
+                };
+                if let Err(e) = route::validate_pattern(&route) {
+                    return corrupt(format!("invalid route {}: {}", leaf, e));
+                }
+                continue;
+            }
+            Tree::Specific(name, _) => {
+                if name.starts_with(':') || name.starts_with('*') || name.contains('/') {
+                    return corrupt(format!("invalid name for a specific part: {}", name));
+                }
+                name.clone()
+            }
+            Tree::Parameter(name, _, _) => {
+                if !name.starts_with(':') {
+                    return corrupt(format!("parameters have to start with ':': {}", name));
+                }
+                segment_of(c)
+            }
+            Tree::Wildcard(_) => segment_of(c),
+        };
+        if !segment.is_empty() && route::new_pattern(&format!("/{}", segment)).is_err() {
+            return corrupt(format!("invalid part: {}", segment));
+        }
+        pattern.push(segment);
+        match c {
+            Tree::Wildcard(grandchildren) => check_children(grandchildren, true, pattern)?,
+            Tree::Specific(_, grandchildren) | Tree::Parameter(_, _, grandchildren) => {
+                check_children(grandchildren, false, pattern)?
+            }
+            Tree::Leaf(..) => {}
+        }
+        pattern.pop();
+    }
+    Ok(())
+}
+
+/// The type of a part of a route as it has been added, see new_node.
+fn part_kind(segment: &str) -> PartKind {
+    if segment.starts_with('*') {
+        PartKind::Wildcard
+    } else if segment.starts_with(':') {
+        PartKind::Parameter
+    } else {
+        PartKind::Specific
+    }
+}
+
+/// Builds the error for a route that collides with the node at this level. `parents` holds the
+/// parts of the tree down to the node, the existing route is the first route below it.
+fn collision<T>(
+    kind: CollisionKind,
+    route: &Route,
+    segment: usize,
+    parents: &[String],
+    node: &Tree<T>,
+) -> AddRouteError {
+    let mut routes = Vec::new();
+    collect_routes(
+        std::slice::from_ref(node),
+        &mut parents.to_vec(),
+        &mut routes,
+    );
+    let existing = match routes.into_iter().next() {
+        Some((pattern, _)) => pattern,
+        //a branch left behind without routes, the node itself is in the way
+        None => {
+            let mut path = parents.to_vec();
+            path.push(segment_of(node));
+            leaf_pattern(&path, &None)
+        }
+    };
+    AddRouteError::Collision(Box::new(Collision {
+        kind,
+        route: pattern_of(route),
+        existing,
+        segment,
+    }))
+}
+
 fn find_matching_child<T>(
-    children: &mut Vec<Tree<T>>,
+    children: &[Tree<T>],
     route: &Route,
     level: usize,
+    case: CaseSensitivity,
+    parents: &[String],
 ) -> Result<Option<usize>, AddRouteError> {
+    if let Some(idx) = find_specific(children, route.path[level], case) {
+        return Ok(Some(idx));
+    }
+
     let mut child_to_add_to: Option<usize> = None;
 
     let mut counter = 0;
 
-    let mut idx = 0;
-    for c in children {
+    for (idx, c) in children.iter().enumerate() {
         match c {
-            Tree::Leaf(_, _) => {
-                idx+=1;
-                continue;
-            }
+            Tree::Leaf(..) => continue,
             Tree::Wildcard(_) => {
-                if route.path[level] != "*" {
-                    return Err(AddRouteError::MismatchTypes(
-                        "Wildcard".to_owned(),
-                        "Specific".to_owned(),
-                    ));
+                if !route.path[level].starts_with('*') {
+                    let kind = CollisionKind::MismatchTypes(
+                        PartKind::Wildcard,
+                        part_kind(route.path[level]),
+                    );
+                    return Err(collision(kind, route, level, parents, c));
                 } else {
                     child_to_add_to = Some(idx);
                     counter += 1;
                 }
             }
-            Tree::Specific(name, _) => {
-                if route.path[level] == name.as_str() {
-                    child_to_add_to = Some(idx);
-                    counter += 1;
-                }
-            }
-            Tree::Parameter(name, _) => {
-                if route.path[level] == name.as_str() {
+            Tree::Specific(_, _) => continue,
+            Tree::Parameter(_, _, _) => {
+                if is_node_for_segment(c, route.path[level]) {
                     child_to_add_to = Some(idx);
                     counter += 1;
                 } else {
-                    return Err(AddRouteError::MismatchParameter(
-                        name.clone(),
-                        route.path[level].to_owned(),
-                    ));
+                    let kind = match part_kind(route.path[level]) {
+                        PartKind::Parameter => CollisionKind::MismatchParameter,
+                        other => CollisionKind::MismatchTypes(PartKind::Parameter, other),
+                    };
+                    return Err(collision(kind, route, level, parents, c));
                 }
             }
         }
-        idx += 1;
     }
 
     if counter > 1 {
-        panic!("Bug detected");
+        return Err(AddRouteError::CorruptTree(format!(
+            "more than one child matches {}",
+            route.path[level]
+        )));
     }
 
     Ok(child_to_add_to)
 }
 
+/// How a route is added besides the route itself.
+struct AddOptions {
+    strict: bool,
+    /// The case sensitivity the route is matched with, used to find existing specific parts.
+    case: CaseSensitivity,
+    /// Stored in the leaf if the route does not use the case sensitivity of the router.
+    case_override: Option<CaseSensitivity>,
+}
+
+/// Adds the route below the tree. `parents` holds the parts of the nodes the route has been added
+/// to so far, they are needed to report which route is in the way if it collides.
 fn add_route<T>(
     tree: &mut Tree<T>,
     route: &Route,
     level: usize,
     item: T,
+    options: &AddOptions,
+    parents: &mut Vec<String>,
 ) -> Result<(), AddRouteError> {
     let children = match tree {
-        Tree::Leaf(_, _) => {
-            //bug -> panic
-            panic!("Tried to add leaf to a leaf: {:?}", route.path);
+        Tree::Leaf(..) => {
+            return Err(AddRouteError::CorruptTree(format!(
+                "tried to add {} below a leaf",
+                route
+            )));
         }
-        Tree::Specific(_, children) => (children),
-        Tree::Parameter(_, children) => (children),
-        Tree::Wildcard(children) => (children),
+        Tree::Specific(_, children) => children,
+        Tree::Parameter(_, _, children) => children,
+        Tree::Wildcard(children) => children,
     };
 
     if level == route.path.len() {
-        for c in &*children {
-            match c {
-                Tree::Leaf(_, _) => {
-                    return Err(AddRouteError::RouteExists(route.path.join("/").to_owned()));
+        if let Some(leaf) = children.iter().find(|c| matches!(c, Tree::Leaf(..))) {
+            let kind = CollisionKind::RouteExists;
+            return Err(collision(kind, route, level - 1, parents, leaf));
+        }
+        children.push(Tree::Leaf(
+            item,
+            catch_all_name(route.path[level - 1]),
+            options.case_override,
+        ));
+        return Ok(());
+    }
+
+    let name: &str = route.path[level];
+    let idx = if options.strict {
+        match find_matching_child(children, route, level, options.case, parents)? {
+            Some(idx) => idx,
+            None => {
+                //need to add new child depending on the part of the route
+                if name.starts_with('*') || name.starts_with(':') {
+                    //leaves end other routes here, they do not collide with the new part
+                    let other = children.iter().find(|c| !matches!(c, Tree::Leaf(..)));
+                    if let Some(c) = other {
+                        let kind = CollisionKind::MismatchTypes(
+                            part_kind(&segment_of(c)),
+                            part_kind(name),
+                        );
+                        return Err(collision(kind, route, level, parents, c));
+                    }
                 }
-                _ => continue,
+                insert_child(children, new_node(name))
             }
         }
-        let chatch_all = route.path[level - 1] == "*";
-        children.push(Tree::Leaf(item, chatch_all));
-        Ok(())
     } else {
-        match find_matching_child(children, route, level) {
-            Err(e) => return Err(e),
-            Ok(idx) => {
-                match idx {
-                    Some(idx) => {
-                        return add_route(&mut children[idx], route, level + 1, item);
-                    }
-                    None => {
-                        //need to add new child depending on the part of the route
-                        let name: &str = route.path[level];
-                        if name == "*" {
-                            if children.len() > 0 {
-                                return Err(AddRouteError::MismatchTypes(
-                                    "Specific/Parameter".to_owned(),
-                                    "Wildcard".to_owned(),
+        let existing = find_specific(children, name, options.case)
+            .or_else(|| children.iter().position(|c| is_node_for_segment(c, name)));
+        match existing {
+            Some(idx) => idx,
+            None => {
+                if name.starts_with(':') {
+                    //two parameters with different names but the same constraint would always
+                    //match the same parts
+                    let (_, constraint) = parameter_parts(name);
+                    for c in &*children {
+                        if let Tree::Parameter(_, other_constraint, _) = c {
+                            if *other_constraint == constraint {
+                                return Err(collision(
+                                    CollisionKind::MismatchParameter,
+                                    route,
+                                    level,
+                                    parents,
+                                    c,
                                 ));
                             }
-                            children.push(Tree::Wildcard(Vec::new()));
-                        } else {
-                            if name.starts_with(":") {
-                                if children.len() > 0 {
-                                    for c in &*children {
-                                        match c {
-                                            Tree::Leaf(_, _) => continue,
-                                            _ => {
-                                                return Err(AddRouteError::MismatchParameter(
-                                                    name.to_owned(),
-                                                    format!(
-                                                        "other: \"{}\"",
-                                                        match c {
-                                                            Tree::Leaf(_, _) => "Leaf".to_owned(),
-                                                            Tree::Wildcard(_) => {
-                                                                "Wildcard".to_owned()
-                                                            }
-                                                            Tree::Parameter(name, _) => {
-                                                                format!("Param: {}", name)
-                                                                    .to_owned()
-                                                            }
-                                                            Tree::Specific(name, _) => {
-                                                                format!("Specific: {}", name)
-                                                            }
-                                                        }
-                                                    ),
-                                                ));
-                                            }
-                                        }
-                                    }
-                                }
-                                children.push(Tree::Parameter(name.to_owned(), Vec::new()));
-                            } else {
-                                children.push(Tree::Specific(name.to_owned(), Vec::new()));
-                            }
                         }
-                        return add_route(children.last_mut().unwrap(), route, level + 1, item);
                     }
                 }
+                insert_child(children, new_node(name))
+            }
+        }
+    };
+    parents.push(segment_of(&children[idx]));
+    add_route(&mut children[idx], route, level + 1, item, options, parents)
+}
+
+/// Finds the specific child that the segment would be matched against with this case sensitivity.
+/// A child with exactly this name is preferred over one that only differs in case.
+fn find_specific<T>(children: &[Tree<T>], segment: &str, case: CaseSensitivity) -> Option<usize> {
+    if segment.starts_with(':') || segment.starts_with('*') {
+        return None;
+    }
+    let mut folded = None;
+    for (idx, c) in children.iter().enumerate() {
+        if let Tree::Specific(name, _) = c {
+            match case::required_mode(name, segment, case) {
+                Some(CaseSensitivity::Sensitive) => return Some(idx),
+                Some(_) if folded.is_none() => folded = Some(idx),
+                _ => {}
             }
         }
     }
+    folded
 }
 
-use std::collections::HashMap;
-fn find_route<'a, T>(
-    tree: &'a mut Tree<T>,
+/// Routes ending in a wildcard match any longer route. The rest of the route is captured with the
+/// name of the wildcard (`*rest` -> `rest`) or as `*` if the wildcard has no name.
+fn catch_all_name(last_segment: &str) -> Option<String> {
+    match last_segment {
+        "*" => Some("*".to_owned()),
+        wildcard if wildcard.starts_with('*') => Some(wildcard[1..].to_owned()),
+        _ => None,
+    }
+}
+
+fn new_node<T>(name: &str) -> Tree<T> {
+    if name.starts_with('*') {
+        Tree::Wildcard(Vec::new())
+    } else if name.starts_with(':') {
+        let (name, constraint) = parameter_parts(name);
+        Tree::Parameter(name.to_owned(), constraint, Vec::new())
+    } else {
+        Tree::Specific(name.to_owned(), Vec::new())
+    }
+}
+
+/// Checks whether the child was created for this segment of a route when it was added. Unlike
+/// find_route this does not match requests against the tree, the segment has to be the same.
+fn is_node_for_segment<T>(child: &Tree<T>, segment: &str) -> bool {
+    match child {
+        Tree::Leaf(..) => false,
+        Tree::Wildcard(_) => segment.starts_with('*'),
+        Tree::Specific(name, _) => percent::decoded_eq(name, segment),
+        Tree::Parameter(name, constraint, _) => {
+            let (segment_name, segment_constraint) = parameter_parts(segment);
+            name.as_str() == segment_name && *constraint == segment_constraint
+        }
+    }
+}
+
+/// Splits a parameter segment into its name and constraint. Routes created by new_route have been
+/// validated already, if the constraint is malformed anyways the whole segment is used as the name.
+fn parameter_parts(segment: &str) -> (&str, Option<Constraint>) {
+    constraint::split_parameter(segment).unwrap_or((segment, None))
+}
+
+/// The part of a route that the node was created for.
+fn segment_of<T>(tree: &Tree<T>) -> String {
+    match tree {
+        Tree::Leaf(..) => String::new(),
+        Tree::Wildcard(_) => "*".to_owned(),
+        Tree::Specific(name, _) => name.clone(),
+        Tree::Parameter(name, None, _) => name.clone(),
+        Tree::Parameter(name, Some(constraint), _) => format!("{}<{}>", name, constraint),
+    }
+}
+
+/// The pattern of the route that ends in a leaf. `pattern` holds the parts of the route down to
+/// the leaf, the wildcard in front of a catch-all leaf gets its name back.
+fn leaf_pattern(pattern: &[String], catch_all: &Option<String>) -> RoutePattern {
+    let mut path = pattern.to_vec();
+    if let (Some(name), Some(last)) = (catch_all, path.last_mut()) {
+        if name != "*" {
+            *last = format!("*{}", name);
+        }
+    }
+    RouteBuf {
+        path,
+        query: None,
+        fragment: None,
+    }
+}
+
+/// The pattern of a route as it is added, without its query and fragment.
+pub(crate) fn pattern_of(route: &Route) -> RoutePattern {
+    RouteBuf {
+        path: route.path.iter().map(|p| p.to_string()).collect(),
+        query: None,
+        fragment: None,
+    }
+}
+
+/// A route taken out of a tree: its pattern, its item and the case sensitivity it overrides.
+type OwnedRoute<T> = (RoutePattern, T, Option<CaseSensitivity>);
+
+/// Takes all routes out of the children. `pattern` holds the parts of the route down to them.
+fn into_routes<T>(
+    children: Vec<Tree<T>>,
+    pattern: &mut Vec<String>,
+    routes: &mut Vec<OwnedRoute<T>>,
+) {
+    for c in children {
+        let segment = segment_of(&c);
+        match c {
+            Tree::Leaf(item, catch_all, case_override) => {
+                routes.push((leaf_pattern(pattern, &catch_all), item, case_override));
+            }
+            Tree::Specific(_, grandchildren)
+            | Tree::Parameter(_, _, grandchildren)
+            | Tree::Wildcard(grandchildren) => {
+                pattern.push(segment);
+                into_routes(grandchildren, pattern, routes);
+                pattern.pop();
+            }
+        }
+    }
+}
+
+/// A copy of the tree with the same routes but without their items.
+fn without_items<T>(tree: &Tree<T>) -> Tree<()> {
+    let copy = |children: &[Tree<T>]| children.iter().map(without_items).collect();
+    match tree {
+        Tree::Wildcard(children) => Tree::Wildcard(copy(children)),
+        Tree::Specific(name, children) => Tree::Specific(name.clone(), copy(children)),
+        Tree::Parameter(name, constraint, children) => {
+            Tree::Parameter(name.clone(), constraint.clone(), copy(children))
+        }
+        Tree::Leaf(_, catch_all, case_override) => {
+            Tree::Leaf((), catch_all.clone(), *case_override)
+        }
+    }
+}
+
+/// The path of a route of a mounted router below the prefix.
+fn mounted_path(prefix: &Route, pattern: &[String]) -> Vec<String> {
+    //the first part of the pattern is the empty part in front of its leading slash
+    let parts = pattern.iter().skip(1).map(String::as_str);
+    prefix
+        .path
+        .iter()
+        .copied()
+        .chain(parts)
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Adds the routes of a router that is mounted below the prefix. Routes that have the case
+/// sensitivity of their router get it as their override if it differs from the one of the router
+/// they are added to.
+fn add_mounted<T>(
+    router: &mut Router<T>,
+    prefix: &Route,
+    routes: Vec<OwnedRoute<T>>,
+    child_case: CaseSensitivity,
+) -> Result<(), AddRouteError> {
+    for (pattern, item, case_override) in routes {
+        let path = mounted_path(prefix, &pattern.path);
+        let route = Route {
+            path: path.iter().map(String::as_str).collect(),
+            query: None,
+            fragment: None,
+        };
+        let case_override = case_override.or(if child_case == router.case {
+            None
+        } else {
+            Some(child_case)
+        });
+        let case = case_override.unwrap_or(router.case);
+        router
+            .add_route_with_options(&route, item, case, case_override)
+            .map_err(|e| AddRouteError::MountCollision(route.to_string(), Box::new(e)))?;
+    }
+    Ok(())
+}
+
+/// Collects the patterns and items of all routes below the children.
+fn collect_routes<'a, T>(
+    children: &'a [Tree<T>],
+    pattern: &mut Vec<String>,
+    routes: &mut Vec<(RoutePattern, &'a T)>,
+) {
+    for c in children {
+        match c {
+            Tree::Leaf(item, catch_all, _) => routes.push((leaf_pattern(pattern, catch_all), item)),
+            Tree::Specific(_, grandchildren)
+            | Tree::Parameter(_, _, grandchildren)
+            | Tree::Wildcard(grandchildren) => {
+                pattern.push(segment_of(c));
+                collect_routes(grandchildren, pattern, routes);
+                pattern.pop();
+            }
+        }
+    }
+}
+
+fn collect_routes_mut<'a, T>(
+    children: &'a mut [Tree<T>],
+    pattern: &mut Vec<String>,
+    routes: &mut Vec<(RoutePattern, &'a mut T)>,
+) {
+    for c in children {
+        let segment = segment_of(c);
+        match c {
+            Tree::Leaf(item, catch_all, _) => routes.push((leaf_pattern(pattern, catch_all), item)),
+            Tree::Specific(_, grandchildren)
+            | Tree::Parameter(_, _, grandchildren)
+            | Tree::Wildcard(grandchildren) => {
+                pattern.push(segment);
+                collect_routes_mut(grandchildren, pattern, routes);
+                pattern.pop();
+            }
+        }
+    }
+}
+
+fn count_routes<T>(children: &[Tree<T>]) -> usize {
+    children
+        .iter()
+        .map(|c| match c {
+            Tree::Leaf(..) => 1,
+            Tree::Specific(_, grandchildren)
+            | Tree::Parameter(_, _, grandchildren)
+            | Tree::Wildcard(grandchildren) => count_routes(grandchildren),
+        })
+        .sum()
+}
+
+/// Finds the leaf of the route that has been added with this pattern and pushes the indices of
+/// the children on the way to it onto the trail. Specific parts are compared like add_route does,
+/// so a route that went into a part with a different case while case was ignored is found with
+/// the pattern it has been added with. `needed` works like in find_route.
+fn find_pattern<T>(
+    children: &[Tree<T>],
     route: &Route,
     level: usize,
-    params: &mut HashMap<String, String>,
-) -> Option<&'a mut T> {
+    lookup: &Lookup<T>,
+    needed: CaseSensitivity,
+    trail: &mut Vec<usize>,
+) -> bool {
     if level == route.path.len() {
-        match tree {
-            Tree::Leaf(item, _) => return Some(item),
-            _ => return None, //this path is longer than the wanted route
+        let leaf = children.iter().position(|c| match c {
+            Tree::Leaf(item, _, case_override) => lookup.accepts(item, *case_override, needed),
+            _ => false,
+        });
+        if let Some(idx) = leaf {
+            trail.push(idx);
         }
-    } else {
-        match tree {
-            Tree::Leaf(item, catch_all) => return if *catch_all { Some(item) } else { None }, //this path is shorter than the wanted route
-            Tree::Specific(name, children) => {
-                if name.as_str() == route.path[level] {
-                    for c in children {
-                        match find_route(c, route, level + 1, params) {
-                            Some(r) => return Some(r),
-                            None => {}
-                        }
-                    }
-                }
+        return leaf.is_some();
+    }
+    let segment = route.path[level];
+    let exact = children
+        .iter()
+        .position(|c| is_node_for_segment(c, segment));
+    //specific parts with a different case come after the exact one
+    let folded = children.iter().enumerate().filter_map(|(idx, c)| match c {
+        Tree::Specific(name, _) if Some(idx) != exact => Some((
+            idx,
+            case::required_mode(name, segment, lookup.loosest_case)?,
+        )),
+        _ => None,
+    });
+    let candidates = exact
+        .map(|idx| (idx, CaseSensitivity::Sensitive))
+        .into_iter()
+        .chain(folded);
+    for (idx, mode) in candidates {
+        let grandchildren = match &children[idx] {
+            Tree::Specific(_, grandchildren)
+            | Tree::Parameter(_, _, grandchildren)
+            | Tree::Wildcard(grandchildren) => grandchildren,
+            Tree::Leaf(..) => continue,
+        };
+        trail.push(idx);
+        if find_pattern(
+            grandchildren,
+            route,
+            level + 1,
+            lookup,
+            needed.max(mode),
+            trail,
+        ) {
+            return true;
+        }
+        trail.pop();
+    }
+    false
+}
+
+/// Removes the leaf at the end of the trail. Branches that are left without any routes are
+/// removed too.
+fn remove_at_trail<T>(children: &mut Vec<Tree<T>>, trail: &[usize]) -> Option<T> {
+    let (idx, rest) = trail.split_first()?;
+    if rest.is_empty() {
+        if !matches!(children.get(*idx)?, Tree::Leaf(..)) {
+            return None;
+        }
+        return match children.remove(*idx) {
+            Tree::Leaf(item, ..) => Some(item),
+            _ => None,
+        };
+    }
+    let item = match children.get_mut(*idx)? {
+        Tree::Specific(_, grandchildren)
+        | Tree::Parameter(_, _, grandchildren)
+        | Tree::Wildcard(grandchildren) => {
+            let item = remove_at_trail(grandchildren, rest)?;
+            if grandchildren.is_empty() {
+                children.remove(*idx);
             }
-            Tree::Parameter(name, children) => {
-                for c in children {
-                    match find_route(c, route, level + 1, params) {
-                        Some(r) => {
-                            params.insert(name.to_owned(), route.path[level].to_owned());
-                            return Some(r);
-                        }
-                        None => {}
-                    }
+            item
+        }
+        Tree::Leaf(..) => return None,
+    };
+    Some(item)
+}
+
+/// Everything a lookup needs besides the tree and the path that stays the same on the way down.
+struct Lookup<'a, T> {
+    /// Leaves for which this returns false are skipped as if they did not match.
+    accept: &'a dyn Fn(&T) -> bool,
+    /// The case sensitivity of leaves that do not override it.
+    case: CaseSensitivity,
+    loosest_case: CaseSensitivity,
+}
+
+impl<'a, T> Lookup<'a, T> {
+    fn accepts(
+        &self,
+        item: &T,
+        case_override: Option<CaseSensitivity>,
+        needed: CaseSensitivity,
+    ) -> bool {
+        needed <= case_override.unwrap_or(self.case) && (self.accept)(item)
+    }
+}
+
+/// A position in the path that is looked up, so paths can be matched without splitting them into
+/// a Route first.
+trait Cursor<'r>: Copy {
+    /// What a catch-all leaf matches.
+    type Rest;
+
+    /// The part at this position and the position after it, None at the end of the path.
+    fn next(self) -> Option<(&'r str, Self)>;
+
+    /// Everything from the part before this position on. That part was matched by the wildcard
+    /// in front of the catch-all leaf.
+    fn rest(self) -> Self::Rest;
+}
+
+/// A position in the parts of a Route.
+#[derive(Clone, Copy)]
+struct RouteCursor<'r> {
+    path: &'r [&'r str],
+    level: usize,
+}
+
+impl<'r> RouteCursor<'r> {
+    fn new(route: &'r Route) -> RouteCursor<'r> {
+        RouteCursor {
+            path: &route.path,
+            level: 0,
+        }
+    }
+}
+
+impl<'r> Cursor<'r> for RouteCursor<'r> {
+    type Rest = &'r [&'r str];
+
+    fn next(self) -> Option<(&'r str, Self)> {
+        let part = self.path.get(self.level)?;
+        Some((
+            part,
+            RouteCursor {
+                path: self.path,
+                level: self.level + 1,
+            },
+        ))
+    }
+
+    fn rest(self) -> &'r [&'r str] {
+        &self.path[self.level - 1..]
+    }
+}
+
+/// A position in a path that has not been split, given by the byte offsets of the next part and
+/// the one before it.
+#[derive(Clone, Copy)]
+struct StrCursor<'r> {
+    path: &'r str,
+    start: usize,
+    previous: usize,
+}
+
+impl<'r> StrCursor<'r> {
+    fn new(path: &'r str) -> StrCursor<'r> {
+        StrCursor {
+            path,
+            start: 0,
+            previous: 0,
+        }
+    }
+}
+
+impl<'r> Cursor<'r> for StrCursor<'r> {
+    type Rest = &'r str;
+
+    fn next(self) -> Option<(&'r str, Self)> {
+        if self.start > self.path.len() {
+            return None;
+        }
+        let end = self.path[self.start..]
+            .find('/')
+            .map_or(self.path.len(), |idx| self.start + idx);
+        Some((
+            &self.path[self.start..end],
+            StrCursor {
+                path: self.path,
+                start: end + 1,
+                previous: self.start,
+            },
+        ))
+    }
+
+    fn rest(self) -> &'r str {
+        &self.path[self.previous..]
+    }
+}
+
+/// Collects what a lookup captures. `'t` is the lifetime of the tree, `'r` the one of the path
+/// and `R` what catch-alls match. Parameters are only captured once the rest of the path matched
+/// below them, so nothing has to be undone when a branch fails.
+trait Captures<'t, 'r, R> {
+    fn parameter(&mut self, name: &'t str, value: &'r str);
+    /// The parts matched by a catch-all leaf.
+    fn rest(&mut self, name: &'t str, rest: R);
+    /// Called with the index of every child that is tried.
+    fn enter(&mut self, _idx: usize) {}
+    /// Called when the child that was entered last did not match.
+    fn leave(&mut self) {}
+    /// Called for every part of the route that matched, from the last part to the first.
+    fn matched(&mut self, _part: MatchedPart) {}
+}
+
+/// Decodes the captured values into Params and keeps the trail of the match, so it can be
+/// followed again with mutable access.
+#[derive(Default)]
+struct OwnedCaptures {
+    params: Params,
+    trail: Vec<usize>,
+}
+
+/// The parts matched by a catch-all, decoded one by one for Params.
+trait DecodeRest {
+    fn decode(self) -> Vec<String>;
+}
+
+impl DecodeRest for &[&str] {
+    fn decode(self) -> Vec<String> {
+        self.iter()
+            .map(|segment| percent::decode_or_raw(segment).into_owned())
+            .collect()
+    }
+}
+
+impl DecodeRest for &str {
+    fn decode(self) -> Vec<String> {
+        //split before decoding, so an encoded slash stays in its part
+        self.split('/')
+            .map(|segment| percent::decode_or_raw(segment).into_owned())
+            .collect()
+    }
+}
+
+impl<'t, 'r, R: DecodeRest> Captures<'t, 'r, R> for OwnedCaptures {
+    fn parameter(&mut self, name: &'t str, value: &'r str) {
+        self.params
+            .insert(name, percent::decode_or_raw(value).into_owned());
+    }
+
+    fn rest(&mut self, name: &'t str, rest: R) {
+        self.params.set_rest(name, rest.decode());
+    }
+
+    fn enter(&mut self, idx: usize) {
+        self.trail.push(idx);
+    }
+
+    fn leave(&mut self) {
+        self.trail.pop();
+    }
+
+    fn matched(&mut self, part: MatchedPart) {
+        self.params.matched(part);
+    }
+}
+
+impl<'t, 'r> Captures<'t, 'r, &'r [&'r str]> for BorrowedParams<'t, 'r> {
+    fn parameter(&mut self, name: &'t str, value: &'r str) {
+        self.push(name, value);
+    }
+
+    fn rest(&mut self, name: &'t str, rest: &'r [&'r str]) {
+        self.set_rest(name, rest);
+    }
+}
+
+/// Matches the path from the cursor on against the tree. `needed` is the strictest case
+/// sensitivity in which the specific parts so far matched, a leaf only matches if its route
+/// allows it.
+fn find_route<'t, 'r, T, P, C>(
+    tree: &'t Tree<T>,
+    lookup: &Lookup<T>,
+    cursor: P,
+    needed: CaseSensitivity,
+    captures: &mut C,
+) -> Option<&'t T>
+where
+    P: Cursor<'r>,
+    C: Captures<'t, 'r, P::Rest>,
+{
+    match (tree, cursor.next()) {
+        (Tree::Leaf(item, catch_all, case_override), next) => {
+            //leaves that are no catch-all only match at the end of the path
+            if next.is_some() && catch_all.is_none() {
+                return None; //this path is shorter than the wanted route
+            }
+            if !lookup.accepts(item, *case_override, needed) {
+                return None;
+            }
+            if let Some(name) = catch_all {
+                captures.rest(name, cursor.rest());
+                captures.matched(MatchedPart::CatchAll);
+            }
+            Some(item)
+        }
+        (_, None) => None, //this path is longer than the wanted route
+        (Tree::Specific(name, children), Some((part, next))) => {
+            let mode = case::required_mode(name, part, lookup.loosest_case)?;
+            let item = find_in_children(children, lookup, next, needed.max(mode), captures)?;
+            captures.matched(if mode == CaseSensitivity::Sensitive {
+                MatchedPart::Specific
+            } else {
+                MatchedPart::SpecificIgnoringCase
+            });
+            Some(item)
+        }
+        (Tree::Parameter(name, constraint, children), Some((part, next))) => {
+            if let Some(constraint) = constraint {
+                if !constraint.matches(&percent::decode_or_raw(part)) {
+                    return None;
                 }
             }
+            let item = find_in_children(children, lookup, next, needed, captures)?;
+            captures.parameter(name, part);
+            captures.matched(if constraint.is_some() {
+                MatchedPart::ConstrainedParameter
+            } else {
+                MatchedPart::Parameter
+            });
+            Some(item)
+        }
+        (Tree::Wildcard(children), Some((_, next))) => {
+            let item = find_in_children(children, lookup, next, needed, captures)?;
+            captures.matched(MatchedPart::Wildcard);
+            Some(item)
+        }
+    }
+}
 
-            Tree::Wildcard(children) => {
-                for c in children {
-                    match find_route(c, route, level + 1, params) {
-                        Some(r) => return Some(r),
-                        None => {}
-                    }
+/// The order in which the different types of children are tried when looking up a route.
+/// Parameters with constraints are tried before those without and routes with catch-all leaves are
+/// only used if no longer route matches.
+fn precedence<T>(tree: &Tree<T>) -> u8 {
+    match tree {
+        Tree::Specific(_, _) => 0,
+        Tree::Parameter(_, Some(_), _) => 1,
+        Tree::Parameter(_, None, _) => 2,
+        Tree::Wildcard(_) => 3,
+        Tree::Leaf(..) => 4,
+    }
+}
+
+/// Children are kept in the order of their precedence and specific parts are sorted by their
+/// decoded names, so lookups can find them with a binary search instead of comparing all of them.
+/// The names are sorted by their case folding first, so the parts a lookup that ignores case has to
+/// try are next to each other as well.
+/// Parameters with constraints are sorted by their constraints (see Constraint::precedence_cmp),
+/// so the order in which routes have been added never decides which one matches.
+fn child_order<T>(a: &Tree<T>, b: &Tree<T>) -> Ordering {
+    precedence(a)
+        .cmp(&precedence(b))
+        .then_with(|| match (a, b) {
+            (Tree::Specific(a, _), Tree::Specific(b, _)) => specific_cmp(a, b),
+            (
+                Tree::Parameter(a, Some(a_constraint), _),
+                Tree::Parameter(b, Some(b_constraint), _),
+            ) => a_constraint
+                .precedence_cmp(b_constraint)
+                .then_with(|| a.cmp(b)),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Inserts the child at its place in the order, after all children that are equal to it.
+fn insert_child<T>(children: &mut Vec<Tree<T>>, child: Tree<T>) -> usize {
+    let idx = children.partition_point(|c| child_order(c, &child) != Ordering::Greater);
+    children.insert(idx, child);
+    idx
+}
+
+/// Brings trees that have not been built by add_route into the order it keeps.
+fn sort_children<T>(children: &mut [Tree<T>]) {
+    children.sort_by(child_order);
+    for c in children {
+        match c {
+            Tree::Specific(_, grandchildren)
+            | Tree::Parameter(_, _, grandchildren)
+            | Tree::Wildcard(grandchildren) => sort_children(grandchildren),
+            Tree::Leaf(..) => {}
+        }
+    }
+}
+
+/// The number of specific children, they come first.
+fn count_specific<T>(children: &[Tree<T>]) -> usize {
+    children.partition_point(|c| matches!(c, Tree::Specific(_, _)))
+}
+
+/// The order of the names of specific children, see child_order.
+fn specific_cmp(a: &str, b: &str) -> Ordering {
+    case::folded_cmp(a, b).then_with(|| percent::decoded_cmp(a, b))
+}
+
+/// Finds the specific child whose decoded name is exactly the decoded segment.
+fn find_exact_specific<T>(children: &[Tree<T>], segment: &str) -> Option<usize> {
+    children[..count_specific(children)]
+        .binary_search_by(|c| match c {
+            Tree::Specific(name, _) => specific_cmp(name, segment),
+            //count_specific only returns specific children
+            _ => Ordering::Less,
+        })
+        .ok()
+}
+
+/// The specific children whose names have the same case folding as the segment, only they can
+/// match it if case is ignored.
+fn find_folded_specific<T>(children: &[Tree<T>], segment: &str) -> Range<usize> {
+    let specific = &children[..count_specific(children)];
+    let order = |c: &Tree<T>| match c {
+        Tree::Specific(name, _) => case::folded_cmp(name, segment),
+        _ => Ordering::Less,
+    };
+    let start = specific.partition_point(|c| order(c) == Ordering::Less);
+    let end = specific.partition_point(|c| order(c) != Ordering::Greater);
+    start..end
+}
+
+fn find_in_child<'t, 'r, T, P, C>(
+    children: &'t [Tree<T>],
+    idx: usize,
+    lookup: &Lookup<T>,
+    cursor: P,
+    needed: CaseSensitivity,
+    captures: &mut C,
+) -> Option<&'t T>
+where
+    P: Cursor<'r>,
+    C: Captures<'t, 'r, P::Rest>,
+{
+    captures.enter(idx);
+    let item = find_route(&children[idx], lookup, cursor, needed, captures);
+    if item.is_none() {
+        captures.leave();
+    }
+    item
+}
+
+fn find_in_children<'t, 'r, T, P, C>(
+    children: &'t [Tree<T>],
+    lookup: &Lookup<T>,
+    cursor: P,
+    needed: CaseSensitivity,
+    captures: &mut C,
+) -> Option<&'t T>
+where
+    P: Cursor<'r>,
+    C: Captures<'t, 'r, P::Rest>,
+{
+    let specific = count_specific(children);
+    //specific parts can only match if there is a part of the path left
+    if let Some((part, _)) = cursor.next() {
+        let exact = find_exact_specific(children, part);
+        if let Some(idx) = exact {
+            let item = find_in_child(children, idx, lookup, cursor, needed, captures);
+            if item.is_some() {
+                return item;
+            }
+        }
+        //other specific parts can only match if case is ignored
+        if lookup.loosest_case != CaseSensitivity::Sensitive {
+            let folded = find_folded_specific(children, part);
+            for idx in folded.filter(|idx| Some(*idx) != exact) {
+                let item = find_in_child(children, idx, lookup, cursor, needed, captures);
+                if item.is_some() {
+                    return item;
                 }
             }
+        }
+    }
+    for idx in specific..children.len() {
+        let item = find_in_child(children, idx, lookup, cursor, needed, captures);
+        if item.is_some() {
+            return item;
+        }
+    }
+    None
+}
+
+/// The loosest case sensitivity any leaf in the tree overrides the one of the router with.
+fn loosest_case_override<T>(tree: &Tree<T>) -> Option<CaseSensitivity> {
+    match tree {
+        Tree::Leaf(_, _, case_override) => *case_override,
+        Tree::Specific(_, children)
+        | Tree::Parameter(_, _, children)
+        | Tree::Wildcard(children) => children.iter().filter_map(loosest_case_override).max(),
+    }
+}
+
+/// Follows a trail produced by find_route down to the leaf it ends in.
+fn follow_trail_mut<'a, T>(tree: &'a mut Tree<T>, trail: &[usize]) -> Option<&'a mut T> {
+    match (tree, trail.split_first()) {
+        (Tree::Leaf(item, ..), None) => Some(item),
+        (Tree::Specific(_, children), Some((idx, rest)))
+        | (Tree::Parameter(_, _, children), Some((idx, rest)))
+        | (Tree::Wildcard(children), Some((idx, rest))) => {
+            follow_trail_mut(children.get_mut(*idx)?, rest)
+        }
+        _ => None,
+    }
+}
+
+/// The pattern of the route a trail ends in, rebuilt from the parts of the tree along it.
+fn pattern_at_trail<T>(tree: &Tree<T>, trail: &[usize]) -> Option<RoutePattern> {
+    let mut pattern = Vec::with_capacity(trail.len());
+    let mut node = tree;
+    for idx in trail {
+        node = match node {
+            Tree::Specific(_, children)
+            | Tree::Parameter(_, _, children)
+            | Tree::Wildcard(children) => children.get(*idx)?,
+            Tree::Leaf(..) => return None,
         };
-        None
+        if !matches!(node, Tree::Leaf(..)) {
+            pattern.push(segment_of(node));
+        }
+    }
+    match node {
+        Tree::Leaf(_, catch_all, _) => Some(leaf_pattern(&pattern, catch_all)),
+        _ => None,
+    }
+}
+
+/// Follows several trails at once, so the items at their ends can be borrowed mutably together.
+/// Each trail comes with the index of its slot in `items`, the trails have to end in different
+/// leaves.
+fn follow_trails_mut<'a, T>(
+    tree: &'a mut Tree<T>,
+    trails: Vec<(usize, &[usize])>,
+    items: &mut [Option<&'a mut T>],
+) {
+    match tree {
+        Tree::Leaf(item, ..) => {
+            if let Some((slot, _)) = trails.iter().find(|(_, trail)| trail.is_empty()) {
+                items[*slot] = Some(item);
+            }
+        }
+        Tree::Specific(_, children)
+        | Tree::Parameter(_, _, children)
+        | Tree::Wildcard(children) => {
+            for (idx, c) in children.iter_mut().enumerate() {
+                let below: Vec<_> = trails
+                    .iter()
+                    .filter_map(|(slot, trail)| match trail.split_first() {
+                        Some((first, rest)) if *first == idx => Some((*slot, rest)),
+                        _ => None,
+                    })
+                    .collect();
+                if !below.is_empty() {
+                    follow_trails_mut(c, below, items);
+                }
+            }
+        }
     }
 }
 
 impl<T> Router<T> {
+    /// Creates a router from a tree that has been built by hand or taken from another router. The
+    /// tree is checked to be in a state that add_route could have produced, otherwise
+    /// RouterError::CorruptTree is returned. The router allows mixed parts like new_router.
+    #[allow(dead_code)]
+    pub fn from_tree(mut tree: Tree<T>) -> Result<Router<T>, RouterError> {
+        match &mut tree {
+            Tree::Wildcard(root_children) => {
+                for c in root_children.iter() {
+                    if let Tree::Leaf(..) = c {
+                        return Err(RouterError::CorruptTree(
+                            "the root must not contain leaves".to_owned(),
+                        ));
+                    }
+                }
+                check_children(root_children, false, &mut Vec::new())?;
+                sort_children(root_children);
+            }
+            _ => {
+                return Err(RouterError::CorruptTree(
+                    "the root has to be a wildcard".to_owned(),
+                ))
+            }
+        }
+        let loosest_case = loosest_case_override(&tree).unwrap_or(CaseSensitivity::Sensitive);
+        Ok(Router {
+            tree,
+            strict: false,
+            names: HashMap::new(),
+            normalization: Normalization::Strict,
+            case: CaseSensitivity::Sensitive,
+            loosest_case,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn tree(&self) -> &Tree<T> {
+        &self.tree
+    }
+
+    #[allow(dead_code)]
+    pub fn into_tree(self) -> Tree<T> {
+        self.tree
+    }
+
+    fn root_children(&self) -> &[Tree<T>] {
+        match &self.tree {
+            Tree::Wildcard(root_children) => root_children,
+            //from_tree does not accept other roots
+            _ => &[],
+        }
+    }
+
     #[allow(dead_code)]
     pub fn add_route(&mut self, route: &Route, item: T) -> Result<(), AddRouteError> {
-        add_route(&mut self.tree, route, 0, item)
+        self.add_route_with_options(route, item, self.case, None)
     }
 
+    /// Adds the route like add_route, but its specific parts are compared with the given case
+    /// sensitivity instead of the one of the router.
     #[allow(dead_code)]
-    pub fn route<'a>(&'a mut self, route: &Route) -> Option<(&'a mut T, HashMap<String, String>)> {
-        match &mut self.tree {
-            Tree::Wildcard(root_children) => {
-                let mut params = HashMap::new();
-                for c in root_children {
-                    params.clear();
-                    let item = find_route(c, route, 0, &mut params);
-                    match item {
-                        Some(i) => return Some((i, params)),
-                        None => {}
-                    }
+    pub fn add_route_with_case(
+        &mut self,
+        route: &Route,
+        item: T,
+        case: CaseSensitivity,
+    ) -> Result<(), AddRouteError> {
+        self.add_route_with_options(route, item, case, Some(case))
+    }
+
+    fn add_route_with_options(
+        &mut self,
+        route: &Route,
+        item: T,
+        case: CaseSensitivity,
+        case_override: Option<CaseSensitivity>,
+    ) -> Result<(), AddRouteError> {
+        if route.path.is_empty() {
+            return Err(AddRouteError::EmptyRoute);
+        }
+        route::validate_pattern(route).map_err(AddRouteError::MalformedPattern)?;
+        let options = AddOptions {
+            strict: self.strict,
+            case,
+            case_override,
+        };
+        add_route(&mut self.tree, route, 0, item, &options, &mut Vec::new())?;
+        self.loosest_case = self.loosest_case.max(case);
+        Ok(())
+    }
+
+    /// Sets how the specific parts of routes are compared when looking up routes. Routes that have
+    /// been added with add_route_with_case keep their own case sensitivity. Routes that only
+    /// differ in case can not be added while the comparison ignores case.
+    #[allow(dead_code)]
+    pub fn set_case_sensitivity(&mut self, case: CaseSensitivity) {
+        self.case = case;
+        self.loosest_case = self.loosest_case.max(case);
+    }
+
+    #[allow(dead_code)]
+    pub fn case_sensitivity(&self) -> CaseSensitivity {
+        self.case
+    }
+
+    /// Adds the route like add_route and remembers it under the name, so urls for it can be built
+    /// with url_for.
+    #[allow(dead_code)]
+    pub fn add_named_route(
+        &mut self,
+        name: &str,
+        route: &Route,
+        item: T,
+    ) -> Result<(), AddRouteError> {
+        if self.names.contains_key(name) {
+            return Err(AddRouteError::NameExists(name.to_owned()));
+        }
+        self.add_route(route, item)?;
+        self.names.insert(name.to_owned(), route.to_route_buf());
+        Ok(())
+    }
+
+    /// All routes with their items, in the order of the tree. The patterns are the routes as they
+    /// were added, with parameters, constraints and wildcards.
+    #[allow(dead_code)]
+    pub fn iter(&self) -> impl Iterator<Item = (RoutePattern, &T)> {
+        let mut routes = Vec::new();
+        collect_routes(self.root_children(), &mut Vec::new(), &mut routes);
+        routes.into_iter()
+    }
+
+    #[allow(dead_code)]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (RoutePattern, &mut T)> {
+        let mut routes = Vec::new();
+        if let Tree::Wildcard(root_children) = &mut self.tree {
+            collect_routes_mut(root_children, &mut Vec::new(), &mut routes);
+        }
+        routes.into_iter()
+    }
+
+    /// The number of routes.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        count_routes(self.root_children())
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Checks whether a route has been added with this pattern. Unlike get this does not match the
+    /// route against the patterns, `/users/:id` is contained but `/users/7` is not.
+    #[allow(dead_code)]
+    pub fn contains_route(&self, route: &Route) -> bool {
+        self.pattern_trail(route).is_some()
+    }
+
+    /// Adds all routes of the child router below the prefix, e.g. mounting a router with `/users`
+    /// at `/admin/:tenant` adds `/admin/:tenant/users`. Parameters of the prefix are captured
+    /// together with those of the child's routes, so their names must not be used by the child's
+    /// routes too. The names of the child's routes are kept and its routes keep the case
+    /// sensitivity they had in the child, which then also applies to the prefix.
+    ///
+    /// The routes are added to a copy of the tree first, so either all of them are added or none.
+    /// If one of them can not be added, MountCollision is returned with the full route and the
+    /// reason, together with the child router. A prefix that ends with a named wildcard is
+    /// rejected with MalformedPattern.
+    #[allow(dead_code)]
+    pub fn mount(&mut self, prefix: &Route, child: Router<T>) -> Result<(), MountError<T>> {
+        if let Err(error) = self.check_mount(prefix, &child) {
+            return Err(MountError {
+                error,
+                child: Box::new(child),
+            });
+        }
+        let mut routes = Vec::new();
+        if let Tree::Wildcard(children) = child.tree {
+            into_routes(children, &mut Vec::new(), &mut routes);
+        }
+        let added = add_mounted(self, prefix, routes, child.case);
+        debug_assert!(added.is_ok(), "checked by check_mount: {:?}", added);
+        for (name, pattern) in child.names {
+            let pattern = RouteBuf {
+                path: mounted_path(prefix, &pattern.path),
+                query: None,
+                fragment: None,
+            };
+            self.names.insert(name, pattern);
+        }
+        Ok(())
+    }
+
+    /// Mounts the routes of the child on a copy of the tree that has no items, to find out
+    /// whether mount would fail without changing the router.
+    fn check_mount(&self, prefix: &Route, child: &Router<T>) -> Result<(), AddRouteError> {
+        if prefix.path.is_empty() {
+            return Err(AddRouteError::EmptyRoute);
+        }
+        route::validate_prefix(prefix).map_err(AddRouteError::MalformedPattern)?;
+        if let Some(name) = child
+            .names
+            .keys()
+            .find(|name| self.names.contains_key(*name))
+        {
+            return Err(AddRouteError::NameExists(name.clone()));
+        }
+        let mut copy = Router {
+            tree: without_items(&self.tree),
+            strict: self.strict,
+            names: HashMap::new(),
+            normalization: self.normalization,
+            case: self.case,
+            loosest_case: self.loosest_case,
+        };
+        let mut routes = Vec::new();
+        if let Tree::Wildcard(children) = without_items(&child.tree) {
+            into_routes(children, &mut Vec::new(), &mut routes);
+        }
+        add_mounted(&mut copy, prefix, routes, child.case)
+    }
+
+    /// Removes the route that has been added with exactly this pattern and returns the item that was
+    /// stored for it. Branches of the tree that are left without any routes are removed too, so
+    /// routes that would have collided with the removed one can be added afterwards. While case is
+    /// ignored, the pattern may differ in case from the route like it may when adding routes.
+    #[allow(dead_code)]
+    pub fn remove_route(&mut self, route: &Route) -> Option<T> {
+        let trail = self.pattern_trail(route)?;
+        //names of the route may have been given with a different case
+        let names: Vec<String> = self
+            .names
+            .iter()
+            .filter(|(_, pattern)| self.pattern_trail(&pattern.as_route()).as_ref() == Some(&trail))
+            .map(|(name, _)| name.clone())
+            .collect();
+        let item = match &mut self.tree {
+            Tree::Wildcard(root_children) => remove_at_trail(root_children, &trail),
+            //from_tree does not accept other roots
+            _ => None,
+        }?;
+        for name in names {
+            self.names.remove(&name);
+        }
+        Some(item)
+    }
+
+    /// Builds the url for the route that has been added with this name. Every parameter of the
+    /// route needs a value (given with or without the leading colon), wildcards take the value
+    /// of their name or of "*" if they have none. The values are percent-encoded, only the slashes
+    /// in the value for a wildcard at the end of the route are kept. Values (or parts between
+    /// those slashes) that are empty, `.` or `..` are rejected with InvalidParameter.
+    #[allow(dead_code)]
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let pattern = self
+            .names
+            .get(name)
+            .ok_or_else(|| UrlError::UnknownRoute(name.to_owned()))?;
+        build_url(pattern, params)
+    }
+
+    #[allow(dead_code)]
+    pub fn route<'a>(&'a mut self, route: &Route) -> Option<(&'a mut T, Params)> {
+        self.route_filtered(route, &|_| true)
+    }
+
+    /// Like route but only needs a shared reference to the router, so lookups can happen while
+    /// other borrows of the router are alive.
+    #[allow(dead_code)]
+    pub fn get<'a>(&'a self, route: &Route) -> Option<(&'a T, Params)> {
+        self.get_filtered(route, &|_| true)
+    }
+
+    /// Looks up all routes that match, e.g. for middleware that applies to `/api/*` as well as to
+    /// `/api/:version/*`. The matches come in the order route tries them, so the first one is
+    /// the one route returns: at the first part where two routes differ, specific parts come
+    /// before parameters (those with constraints first), then wildcards and then catch-alls. Each
+    /// match has its own parameters.
+    ///
+    /// New routers allow overlapping routes next to each other, strict routers only overlap with
+    /// catch-alls.
+    #[allow(dead_code)]
+    pub fn route_all<'a>(&'a mut self, route: &Route) -> Vec<(&'a mut T, Params)> {
+        let captures: Vec<OwnedCaptures> = self
+            .find_all(route)
+            .into_iter()
+            .map(|(_, captures)| captures)
+            .collect();
+        let trails = captures
+            .iter()
+            .enumerate()
+            .map(|(slot, c)| (slot, c.trail.as_slice()))
+            .collect();
+        let mut items: Vec<Option<&mut T>> = captures.iter().map(|_| None).collect();
+        follow_trails_mut(&mut self.tree, trails, &mut items);
+        items
+            .into_iter()
+            .zip(captures)
+            .filter_map(|(item, captures)| Some((item?, captures.params)))
+            .collect()
+    }
+
+    /// Like route_all but only needs a shared reference to the router.
+    #[allow(dead_code)]
+    pub fn get_all<'a>(&'a self, route: &Route) -> Vec<(&'a T, Params)> {
+        self.find_all(route)
+            .into_iter()
+            .map(|(item, captures)| (item, captures.params))
+            .collect()
+    }
+
+    /// Looks up the route like get, but without allocating. The parameters borrow their names from
+    /// the router and their values from the route, so they are not percent-decoded.
+    #[allow(dead_code)]
+    pub fn get_borrowed<'t, 'r>(
+        &'t self,
+        route: &'r Route,
+    ) -> Option<(&'t T, BorrowedParams<'t, 'r>)> {
+        let mut params = BorrowedParams::new(route.query);
+        let item = self.find(RouteCursor::new(route), &|_| true, &mut params)?;
+        Some((item, params))
+    }
+
+    #[allow(dead_code)]
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+    }
+
+    #[allow(dead_code)]
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Looks up the route like route, but takes it as a string and matches it without splitting it
+    /// into a Route first. Routes that new_route rejects return the same errors.
+    #[allow(dead_code)]
+    pub fn route_str<'a>(
+        &'a mut self,
+        path: &str,
+    ) -> Result<Option<(&'a mut T, Params)>, route::MalformedRouteError> {
+        let (path, query, _) = route::check(path)?;
+        let mut captures = OwnedCaptures::default();
+        if self
+            .find(StrCursor::new(path), &|_| true, &mut captures)
+            .is_none()
+        {
+            return Ok(None);
+        }
+        captures.params.set_query(query);
+        Ok(follow_trail_mut(&mut self.tree, &captures.trail).map(|item| (item, captures.params)))
+    }
+
+    /// Like route_str but only needs a shared reference to the router.
+    #[allow(dead_code)]
+    pub fn get_str<'a>(
+        &'a self,
+        path: &str,
+    ) -> Result<Option<(&'a T, Params)>, route::MalformedRouteError> {
+        let (path, query, _) = route::check(path)?;
+        let mut captures = OwnedCaptures::default();
+        Ok(self
+            .find(StrCursor::new(path), &|_| true, &mut captures)
+            .map(|item| {
+                captures.params.set_query(query);
+                (item, captures.params)
+            }))
+    }
+
+    /// Looks up a path as it was received, e.g. from a request line. Unlike route this takes care
+    /// of parsing and, depending on the normalization of the router, of bringing the path into
+    /// its canonical form. Errors are reported for the path that has been parsed, which is the
+    /// canonical form unless the normalization is Strict.
+    #[allow(dead_code)]
+    pub fn route_path<'a>(
+        &'a mut self,
+        path: &str,
+    ) -> Result<PathMatch<&'a mut T>, route::MalformedRouteError> {
+        let (canonical, redirect) = match self.canonical_path(path)? {
+            Some(canonical) => canonical,
+            None => return Ok(PathMatch::NotFound),
+        };
+        if redirect {
+            return Ok(match self.get_str(&canonical)? {
+                Some(_) => PathMatch::Redirect(canonical.to_string()),
+                None => PathMatch::NotFound,
+            });
+        }
+        Ok(match self.route_str(&canonical)? {
+            Some((item, params)) => PathMatch::Found(item, params),
+            None => PathMatch::NotFound,
+        })
+    }
+
+    /// Like route_path but only needs a shared reference to the router.
+    #[allow(dead_code)]
+    pub fn get_path<'a>(
+        &'a self,
+        path: &str,
+    ) -> Result<PathMatch<&'a T>, route::MalformedRouteError> {
+        let (canonical, redirect) = match self.canonical_path(path)? {
+            Some(canonical) => canonical,
+            None => return Ok(PathMatch::NotFound),
+        };
+        Ok(match self.get_str(&canonical)? {
+            Some(_) if redirect => PathMatch::Redirect(canonical.to_string()),
+            Some((item, params)) => PathMatch::Found(item, params),
+            None => PathMatch::NotFound,
+        })
+    }
+
+    /// Returns the path that should be looked up and whether the client should be redirected to
+    /// it instead. None means the path can not match any route (it is the root).
+    fn canonical_path<'p>(
+        &self,
+        path: &'p str,
+    ) -> Result<Option<(Cow<'p, str>, bool)>, route::MalformedRouteError> {
+        if self.normalization == Normalization::Strict {
+            return Ok(Some((Cow::Borrowed(path), false)));
+        }
+        if !path.starts_with('/') {
+            //let new_route report the error
+            route::new_route(path)?;
+        }
+        let canonical = route::normalize(path);
+        if route::split_path(&canonical).0 == "/" {
+            return Ok(None);
+        }
+        let redirect =
+            self.normalization == Normalization::RedirectToCanonical && canonical != path;
+        Ok(Some((Cow::Owned(canonical), redirect)))
+    }
+
+    /// Routes to the first matching item that is accepted by the filter. Items that are rejected
+    /// are skipped and the lookup continues with the next candidate.
+    pub(crate) fn route_filtered<'a>(
+        &'a mut self,
+        route: &Route,
+        accept: &dyn Fn(&T) -> bool,
+    ) -> Option<(&'a mut T, Params)> {
+        let mut captures = OwnedCaptures::default();
+        self.find(RouteCursor::new(route), accept, &mut captures)?;
+        captures.params.set_query(route.query);
+        let item = follow_trail_mut(&mut self.tree, &captures.trail)?;
+        Some((item, captures.params))
+    }
+
+    pub(crate) fn get_filtered<'a>(
+        &'a self,
+        route: &Route,
+        accept: &dyn Fn(&T) -> bool,
+    ) -> Option<(&'a T, Params)> {
+        let mut captures = OwnedCaptures::default();
+        let item = self.find(RouteCursor::new(route), accept, &mut captures)?;
+        captures.params.set_query(route.query);
+        Some((item, captures.params))
+    }
+
+    fn find<'t, 'r, P, C>(
+        &'t self,
+        cursor: P,
+        accept: &dyn Fn(&T) -> bool,
+        captures: &mut C,
+    ) -> Option<&'t T>
+    where
+        P: Cursor<'r>,
+        C: Captures<'t, 'r, P::Rest>,
+    {
+        let lookup = Lookup {
+            accept,
+            case: self.case,
+            loosest_case: self.loosest_case,
+        };
+        find_in_children(
+            self.root_children(),
+            &lookup,
+            cursor,
+            CaseSensitivity::Sensitive,
+            captures,
+        )
+    }
+
+    /// Finds every matching route by looking up the route again and again, skipping the items that
+    /// have been found already.
+    fn find_all<'a>(&'a self, route: &Route) -> Vec<(&'a T, OwnedCaptures)> {
+        let mut matches: Vec<(&'a T, OwnedCaptures)> = Vec::new();
+        loop {
+            let mut captures = OwnedCaptures::default();
+            let accept = |item: &T| !matches.iter().any(|(found, _)| std::ptr::eq(*found, item));
+            match self.find(RouteCursor::new(route), &accept, &mut captures) {
+                Some(item) => {
+                    captures.params.set_query(route.query);
+                    matches.push((item, captures));
                 }
+                None => return matches,
             }
-            //buggy -> panic
-            _ => panic!("Corrupt root"),
+        }
+    }
+
+    /// The trail to the leaf of the route that has been added with this pattern, see find_pattern.
+    fn pattern_trail(&self, route: &Route) -> Option<Vec<usize>> {
+        let lookup = Lookup {
+            accept: &|_| true,
+            case: self.case,
+            loosest_case: self.loosest_case,
         };
+        let mut trail = Vec::new();
+        let found = find_pattern(
+            self.root_children(),
+            route,
+            0,
+            &lookup,
+            CaseSensitivity::Sensitive,
+            &mut trail,
+        );
+        if found {
+            Some(trail)
+        } else {
+            None
+        }
+    }
 
-        None
+    /// Returns the item that has been added with this pattern, together with the pattern as it is
+    /// stored in the tree (which may differ from the given one in case or wildcard names).
+    pub(crate) fn item_for_pattern_mut(&mut self, route: &Route) -> Option<(RoutePattern, &mut T)> {
+        let trail = self.pattern_trail(route)?;
+        let pattern = pattern_at_trail(&self.tree, &trail)?;
+        Some((pattern, follow_trail_mut(&mut self.tree, &trail)?))
     }
 }
@@ -0,0 +1,123 @@
+use super::params::Params;
+use super::route::Route;
+use super::router::{AddRouteError, Router};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A router that can be shared between threads. Lookups work on a snapshot of the router and never
+/// take a lock, changes are made on a copy that replaces the snapshot when it is done. Lookups that
+/// are running keep using the snapshot they started with, later lookups see the change completely.
+///
+/// Changes copy the whole router, so this is meant for route tables that are read much more often
+/// than they are changed.
+///
+/// Readers register in the reader count of the current generation while they take their
+/// reference. A change starts a new generation and only waits for the readers of the previous
+/// one, later readers count in the new generation. The wait is therefore bounded by the few
+/// instructions each of those readers needs, no matter how many lookups keep coming in.
+pub struct SharedRouter<T> {
+    /// Created by Arc::into_raw, this pointer owns one reference to the current snapshot.
+    current: AtomicPtr<Router<T>>,
+    /// Incremented by every change, its lowest bit selects the reader count of the generation.
+    generation: AtomicUsize,
+    /// The number of readers that are between loading `current` and taking their own reference,
+    /// for even and odd generations.
+    readers: [AtomicUsize; 2],
+    /// Only one change is made at a time, otherwise one of them would get lost.
+    writer: Mutex<()>,
+    /// The router is only reached through Arcs, so it can be sent and shared like Arc<Router<T>>.
+    snapshots: PhantomData<Arc<Router<T>>>,
+}
+
+impl<T> SharedRouter<T> {
+    #[allow(dead_code)]
+    pub fn new(router: Router<T>) -> SharedRouter<T> {
+        SharedRouter {
+            current: AtomicPtr::new(Arc::into_raw(Arc::new(router)) as *mut Router<T>),
+            generation: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            writer: Mutex::new(()),
+            snapshots: PhantomData,
+        }
+    }
+
+    /// The router as it is right now. Changes made after this call do not show up in the snapshot.
+    pub fn snapshot(&self) -> Arc<Router<T>> {
+        let readers = loop {
+            let generation = self.generation.load(Ordering::SeqCst);
+            let readers = &self.readers[generation % 2];
+            readers.fetch_add(1, Ordering::SeqCst);
+            //a change that started in between only waits for the other count
+            if self.generation.load(Ordering::SeqCst) == generation {
+                break readers;
+            }
+            readers.fetch_sub(1, Ordering::SeqCst);
+        };
+        let ptr = self.current.load(Ordering::SeqCst);
+        //the writer does not drop the snapshot while the count of its generation is not 0
+        let snapshot = unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        };
+        readers.fetch_sub(1, Ordering::SeqCst);
+        snapshot
+    }
+}
+
+impl<T: Clone> SharedRouter<T> {
+    /// Looks up the route in the current snapshot and returns a copy of the item.
+    #[allow(dead_code)]
+    pub fn get(&self, route: &Route) -> Option<(T, Params)> {
+        self.snapshot()
+            .get(route)
+            .map(|(item, params)| (item.clone(), params))
+    }
+
+    /// Applies the change to a copy of the router. If it returns Ok the copy replaces the current
+    /// snapshot, otherwise it is thrown away and the router stays as it was.
+    pub fn update<R, E, F>(&self, change: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut Router<T>) -> Result<R, E>,
+    {
+        //a poisoned lock only means another change panicked, the snapshot was not replaced then
+        let _guard = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let mut router = Router::clone(&self.snapshot());
+        let result = change(&mut router)?;
+        let new = Arc::into_raw(Arc::new(router)) as *mut Router<T>;
+        let old = self.current.swap(new, Ordering::SeqCst);
+        //readers that may have loaded the old pointer registered in the previous generation, all
+        //readers of the new one see the new pointer
+        let previous = self.generation.fetch_add(1, Ordering::SeqCst);
+        while self.readers[previous % 2].load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+        drop(unsafe { Arc::from_raw(old) });
+        Ok(result)
+    }
+
+    #[allow(dead_code)]
+    pub fn add_route(&self, route: &Route, item: T) -> Result<(), AddRouteError> {
+        self.update(|router| router.add_route(route, item))
+    }
+
+    #[allow(dead_code)]
+    pub fn add_named_route(&self, name: &str, route: &Route, item: T) -> Result<(), AddRouteError> {
+        self.update(|router| router.add_named_route(name, route, item))
+    }
+
+    /// Removes the route like Router::remove_route. The snapshot is only replaced if the route
+    /// existed.
+    #[allow(dead_code)]
+    pub fn remove_route(&self, route: &Route) -> Option<T> {
+        self.update(|router| router.remove_route(route).ok_or(()))
+            .ok()
+    }
+}
+
+impl<T> Drop for SharedRouter<T> {
+    fn drop(&mut self) {
+        drop(unsafe { Arc::from_raw(*self.current.get_mut()) });
+    }
+}
@@ -0,0 +1,50 @@
+/// How a part of a route matched the path, ordered from the least to the most specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MatchedPart {
+    /// The rest of the path was matched by a catch-all. It comes after the wildcard it belongs
+    /// to, so `/a/*` is less specific than `/a/*/b`.
+    CatchAll,
+    Wildcard,
+    Parameter,
+    /// A parameter with a constraint, e.g. `:id<u32>`.
+    ConstrainedParameter,
+    /// A specific part that only matched because case is ignored.
+    SpecificIgnoringCase,
+    Specific,
+}
+
+/// How specific the route is that matched a path, with one entry for each part of the route.
+///
+/// Scores are compared part by part from the start, so the route with the longer static prefix is
+/// the more specific one and after that parameters beat wildcards, which beat catch-alls. A lookup
+/// always returns the matching route with the highest score, no matter in which order the routes
+/// have been added. Parameters with different constraints score the same, they are tried in the
+/// order of their constraints: typed constraints like `<u32>` first (smaller types before larger
+/// ones), then patterns like `<[a-z]+>` ordered by their text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Specificity {
+    parts: Vec<MatchedPart>,
+}
+
+impl Specificity {
+    #[allow(dead_code)]
+    pub fn parts(&self) -> &[MatchedPart] {
+        &self.parts
+    }
+
+    /// The number of specific parts in front of the first parameter or wildcard, including the
+    /// empty part in front of the leading slash.
+    #[allow(dead_code)]
+    pub fn static_prefix(&self) -> usize {
+        self.parts
+            .iter()
+            .take_while(|part| **part >= MatchedPart::SpecificIgnoringCase)
+            .count()
+    }
+
+    /// Lookups find out how the parts matched on the way back from the leaf, so the parts are
+    /// added in front.
+    pub(crate) fn push_front(&mut self, part: MatchedPart) {
+        self.parts.insert(0, part);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::method;
 use crate::route;
 use crate::router;
 #[test]
@@ -23,17 +24,11 @@ fn test_routing() {
 
     let (x, params) = r.route(&route_request).unwrap();
     assert!(*x == 20);
-    assert_eq!(
-        params.get(&":cde".to_owned()),
-        Some(&"this_is_a_param".to_owned())
-    );
+    assert_eq!(params.get_str("cde"), Some("this_is_a_param"));
 
     let (x, params) = r.route(&route_with_ending_wildcard_request).unwrap();
     assert!(*x == 10);
-    assert_eq!(
-        params.get(&":param".to_owned()),
-        Some(&"set_param".to_owned())
-    );
+    assert_eq!(params.get_str("param"), Some("set_param"));
 
     let x = r.route(&route_not_added_request);
     assert_eq!(x, None);
@@ -52,15 +47,15 @@ fn test_routing() {
 
     let (x, p) = r.route(&route_longer_req).unwrap();
     assert!(*x == 123);
-    assert_eq!(p.get(&":param1".to_owned()), Some(&"set1".to_owned()));
-    assert_eq!(p.get(&":param2".to_owned()), Some(&"set2".to_owned()));
+    assert_eq!(p.get_str("param1"), Some("set1"));
+    assert_eq!(p.get_str("param2"), Some("set2"));
     let (x, p) = r.route(&route_shorter_req).unwrap();
     assert!(*x == 456);
-    assert_eq!(p.get(&":param1".to_owned()), Some(&"set1short".to_owned()));
+    assert_eq!(p.get_str("param1"), Some("set1short"));
 
     struct Beep {
         a: u32,
-    };
+    }
 
     let mut r = router::new_router();
     let mut b1 = Beep { a: 10 };
@@ -93,96 +88,2258 @@ fn test_routing() {
     });
 }
 
+fn assert_collision(
+    result: Result<(), router::AddRouteError>,
+    kind: router::CollisionKind,
+    route: &str,
+    existing: &str,
+    segment: usize,
+) {
+    match result {
+        Err(router::AddRouteError::Collision(c)) => {
+            assert_eq!(c.kind, kind);
+            assert_eq!(c.route.to_string(), route);
+            assert_eq!(c.existing.to_string(), existing);
+            assert_eq!(c.segment, segment);
+        }
+        other => panic!("Expected a collision, got {:?}", other),
+    }
+}
+
 #[test]
 fn test_route_collisions() {
+    use crate::case::CaseSensitivity;
+    use router::{CollisionKind, PartKind};
+
     //this checks combinations of collisions that may happen when adding routes.
     //all of these need to error else there is a bug somewhere
 
     //same path twice
-    let mut r = router::new_router();
+    let mut r = router::new_strict_router();
     let route1 = route::new_route("/a/b/c").unwrap();
     let route2 = route::new_route("/a/b/c").unwrap();
     assert!(match r.add_route(&route1, 0) {
         Ok(()) => true,
         Err(_) => false,
     });
-    assert!(match r.add_route(&route2, 0) {
-        Ok(()) => false,
-        Err(_) => true,
-    });
+    assert_collision(
+        r.add_route(&route2, 0),
+        CollisionKind::RouteExists,
+        "/a/b/c",
+        "/a/b/c",
+        3,
+    );
 
     //same path mixed wildcard and specific
-    let mut r = router::new_router();
+    let mut r = router::new_strict_router();
     let route1 = route::new_route("/a/b/c").unwrap();
     let route2 = route::new_route("/a/*/c").unwrap();
     assert!(match r.add_route(&route1, 0) {
         Ok(()) => true,
         Err(_) => false,
     });
-    assert!(match r.add_route(&route2, 0) {
-        Ok(()) => false,
-        Err(_) => true,
-    });
+    assert_collision(
+        r.add_route(&route2, 0),
+        CollisionKind::MismatchTypes(PartKind::Specific, PartKind::Wildcard),
+        "/a/*/c",
+        "/a/b/c",
+        2,
+    );
 
-    let mut r = router::new_router();
+    let mut r = router::new_strict_router();
     let route1 = route::new_route("/a/*/c").unwrap();
     let route2 = route::new_route("/a/b/c").unwrap();
     assert!(match r.add_route(&route1, 0) {
         Ok(()) => true,
         Err(_) => false,
     });
-    assert!(match r.add_route(&route2, 0) {
-        Ok(()) => false,
-        Err(_) => true,
-    });
+    assert_collision(
+        r.add_route(&route2, 0),
+        CollisionKind::MismatchTypes(PartKind::Wildcard, PartKind::Specific),
+        "/a/b/c",
+        "/a/*/c",
+        2,
+    );
 
     //same path mixed parameter and specific
-    let mut r = router::new_router();
+    let mut r = router::new_strict_router();
     let route1 = route::new_route("/a/b/c").unwrap();
     let route2 = route::new_route("/a/:param/c").unwrap();
     assert!(match r.add_route(&route1, 0) {
         Ok(()) => true,
         Err(_) => false,
     });
-    assert!(match r.add_route(&route2, 0) {
-        Ok(()) => false,
-        Err(_) => true,
-    });
+    assert_collision(
+        r.add_route(&route2, 0),
+        CollisionKind::MismatchTypes(PartKind::Specific, PartKind::Parameter),
+        "/a/:param/c",
+        "/a/b/c",
+        2,
+    );
 
-    let mut r = router::new_router();
+    let mut r = router::new_strict_router();
     let route1 = route::new_route("/a/:param/c").unwrap();
     let route2 = route::new_route("/a/b/c").unwrap();
     assert!(match r.add_route(&route1, 0) {
         Ok(()) => true,
         Err(_) => false,
     });
-    assert!(match r.add_route(&route2, 0) {
-        Ok(()) => false,
-        Err(_) => true,
-    });
+    assert_collision(
+        r.add_route(&route2, 0),
+        CollisionKind::MismatchTypes(PartKind::Parameter, PartKind::Specific),
+        "/a/b/c",
+        "/a/:param/c",
+        2,
+    );
 
     //same path mixed wildcard and parameter
-    let mut r = router::new_router();
+    let mut r = router::new_strict_router();
     let route1 = route::new_route("/a/*/c").unwrap();
     let route2 = route::new_route("/a/:param/c").unwrap();
     assert!(match r.add_route(&route1, 0) {
         Ok(()) => true,
         Err(_) => false,
     });
-    assert!(match r.add_route(&route2, 0) {
-        Ok(()) => false,
-        Err(_) => true,
-    });
+    assert_collision(
+        r.add_route(&route2, 0),
+        CollisionKind::MismatchTypes(PartKind::Wildcard, PartKind::Parameter),
+        "/a/:param/c",
+        "/a/*/c",
+        2,
+    );
 
-    let mut r = router::new_router();
+    let mut r = router::new_strict_router();
     let route1 = route::new_route("/a/param/c").unwrap();
     let route2 = route::new_route("/a/*/c").unwrap();
     assert!(match r.add_route(&route1, 0) {
         Ok(()) => true,
         Err(_) => false,
     });
-    assert!(match r.add_route(&route2, 0) {
-        Ok(()) => false,
-        Err(_) => true,
-    });
+    assert_collision(
+        r.add_route(&route2, 0),
+        CollisionKind::MismatchTypes(PartKind::Specific, PartKind::Wildcard),
+        "/a/*/c",
+        "/a/param/c",
+        2,
+    );
+
+    //the existing route is reconstructed from the tree, not from the new route
+    let mut r = router::new_strict_router();
+    r.add_route(&route::new_route("/a/b/c/d").unwrap(), 0)
+        .unwrap();
+    assert_collision(
+        r.add_route(&route::new_route("/a/*").unwrap(), 0),
+        CollisionKind::MismatchTypes(PartKind::Specific, PartKind::Wildcard),
+        "/a/*",
+        "/a/b/c/d",
+        2,
+    );
+    let mut r = router::new_strict_router();
+    r.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    r.add_route(&route::new_route("/A/b/*rest").unwrap(), 0)
+        .unwrap();
+    assert_collision(
+        r.add_route(&route::new_route("/a/B/*other").unwrap(), 0),
+        CollisionKind::RouteExists,
+        "/a/B/*other",
+        "/A/b/*rest",
+        3,
+    );
+
+    //parameters with different names
+    let mut r = router::new_strict_router();
+    r.add_route(&route::new_route("/a/:x/c").unwrap(), 0)
+        .unwrap();
+    assert_collision(
+        r.add_route(&route::new_route("/a/:y").unwrap(), 0),
+        CollisionKind::MismatchParameter,
+        "/a/:y",
+        "/a/:x/c",
+        2,
+    );
+    let mut r = router::new_router();
+    r.add_route(&route::new_route("/a/:x<u32>").unwrap(), 0)
+        .unwrap();
+    r.add_route(&route::new_route("/a/:y").unwrap(), 0).unwrap();
+    assert_collision(
+        r.add_route(&route::new_route("/a/:z<u32>/c").unwrap(), 0),
+        CollisionKind::MismatchParameter,
+        "/a/:z<u32>/c",
+        "/a/:x<u32>",
+        2,
+    );
+}
+
+#[test]
+fn test_routing_immutable() {
+    let mut r: router::Router<u32> = router::new_router();
+    let route1 = route::new_route("/a/:param/c").unwrap();
+    let route2 = route::new_route("/b/*").unwrap();
+    r.add_route(&route1, 1).unwrap();
+    r.add_route(&route2, 2).unwrap();
+
+    let request1 = route::new_route("/a/value/c").unwrap();
+    let request2 = route::new_route("/b/x/y/z").unwrap();
+    let request_missing = route::new_route("/a/value").unwrap();
+
+    //both lookups can be held at the same time because they only borrow the router immutably
+    let (x1, params1) = r.get(&request1).unwrap();
+    let (x2, params2) = r.get(&request2).unwrap();
+    assert_eq!(*x1, 1);
+    assert_eq!(*x2, 2);
+    assert_eq!(params1.get_str("param"), Some("value"));
+    assert_eq!(params2.get_str("*"), Some("x/y/z"));
+    assert!(r.get(&request_missing).is_none());
+
+    //the mutable and the immutable lookup have to agree
+    let (x, params) = r.route(&request1).unwrap();
+    assert_eq!(*x, 1);
+    assert_eq!(params.get_str("param"), Some("value"));
+}
+
+#[test]
+fn test_remove_route() {
+    let mut r: router::Router<u32> = router::new_strict_router();
+    let route1 = route::new_route("/a/b/c").unwrap();
+    let route2 = route::new_route("/a/b/c/d").unwrap();
+    let route3 = route::new_route("/a/:param/c").unwrap();
+    let route4 = route::new_route("/x/*").unwrap();
+    r.add_route(&route1, 1).unwrap();
+    r.add_route(&route2, 2).unwrap();
+    r.add_route(&route4, 4).unwrap();
+
+    //patterns that were never added can not be removed
+    assert_eq!(r.remove_route(&route3), None);
+    assert_eq!(r.remove_route(&route::new_route("/a/b").unwrap()), None);
+
+    //removing a route leaves the longer route with the same prefix in place
+    assert_eq!(r.remove_route(&route1), Some(1));
+    assert_eq!(r.remove_route(&route1), None);
+    assert!(r.get(&route1).is_none());
+    assert_eq!(*r.get(&route2).unwrap().0, 2);
+
+    //the specific branch still exists so the parameter still collides
+    assert!(r.add_route(&route3, 3).is_err());
+
+    //after the last route below "b" is gone the branch is pruned and the parameter fits
+    assert_eq!(r.remove_route(&route2), Some(2));
+    r.add_route(&route3, 3).unwrap();
+    let (x, params) = r.get(&route::new_route("/a/b/c").unwrap()).unwrap();
+    assert_eq!(*x, 3);
+    assert_eq!(params.get_str("param"), Some("b"));
+
+    //wildcards are removed by their pattern, not by a request that matches them
+    assert_eq!(r.remove_route(&route::new_route("/x/y").unwrap()), None);
+    assert_eq!(r.remove_route(&route4), Some(4));
+    assert!(r.get(&route::new_route("/x/y").unwrap()).is_none());
+}
+
+#[test]
+fn test_route_precedence() {
+    //the default router allows the combinations that collide in the strict router
+    //and resolves them as specific > parameter > wildcard
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/a/*/c").unwrap(), 3)
+        .unwrap();
+    r.add_route(&route::new_route("/a/:param/c").unwrap(), 2)
+        .unwrap();
+    r.add_route(&route::new_route("/a/b/c").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/a/:param/d").unwrap(), 4)
+        .unwrap();
+    r.add_route(&route::new_route("/a/*/e").unwrap(), 5)
+        .unwrap();
+
+    let (x, params) = r.get(&route::new_route("/a/b/c").unwrap()).unwrap();
+    assert_eq!(*x, 1);
+    assert!(params.is_empty());
+
+    let (x, params) = r.get(&route::new_route("/a/x/c").unwrap()).unwrap();
+    assert_eq!(*x, 2);
+    assert_eq!(params.get_str("param"), Some("x"));
+
+    //the specific part matches but the rest of the route does not so the parameter is tried next
+    let (x, params) = r.get(&route::new_route("/a/b/d").unwrap()).unwrap();
+    assert_eq!(*x, 4);
+    assert_eq!(params.get_str("param"), Some("b"));
+
+    //parameters that were captured on a branch that did not match are not returned
+    let (x, params) = r.get(&route::new_route("/a/b/e").unwrap()).unwrap();
+    assert_eq!(*x, 5);
+    assert!(params.is_empty());
+
+    assert!(r.get(&route::new_route("/a/b/f").unwrap()).is_none());
+
+    //removing the specific route makes the parameter take over
+    assert_eq!(
+        r.remove_route(&route::new_route("/a/b/c").unwrap()),
+        Some(1)
+    );
+    let (x, _) = r.route(&route::new_route("/a/b/c").unwrap()).unwrap();
+    assert_eq!(*x, 2);
+
+    //longer routes are preferred over catch-alls
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/files/*").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/files/*/meta").unwrap(), 2)
+        .unwrap();
+    assert_eq!(
+        *r.get(&route::new_route("/files/x/meta").unwrap())
+            .unwrap()
+            .0,
+        2
+    );
+    assert_eq!(
+        *r.get(&route::new_route("/files/x/data").unwrap())
+            .unwrap()
+            .0,
+        1
+    );
+
+    //these still collide in the default router
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/a/:param/c").unwrap(), 0)
+        .unwrap();
+    assert!(r
+        .add_route(&route::new_route("/a/:other/d").unwrap(), 0)
+        .is_err());
+    r.add_route(&route::new_route("/a/*/c").unwrap(), 0)
+        .unwrap();
+    assert!(r
+        .add_route(&route::new_route("/a/*/c").unwrap(), 0)
+        .is_err());
+}
+
+#[test]
+fn test_method_routing() {
+    use method::{Method, MethodMatch};
+
+    let mut r: method::MethodRouter<u32> = method::new_method_router();
+    let users = route::new_route("/users/:id").unwrap();
+    let admin = route::new_route("/users/admin").unwrap();
+    r.add(Method::Get, &users, 1).unwrap();
+    r.add(Method::Delete, &users, 2).unwrap();
+    r.add(Method::Post, &admin, 3).unwrap();
+    assert!(r.add(Method::Get, &users, 4).is_err());
+    //the collision names the route as it is stored, which may differ from the new one
+    let mut files: method::MethodRouter<u32> = method::new_method_router();
+    files
+        .add(Method::Get, &route::new_route("/files/*path").unwrap(), 1)
+        .unwrap();
+    match files.add(Method::Get, &route::new_route("/files/*rest").unwrap(), 2) {
+        Err(router::AddRouteError::Collision(c)) => {
+            assert_eq!(c.kind, router::CollisionKind::RouteExists);
+            assert_eq!(c.route.to_string(), "/files/*rest");
+            assert_eq!(c.existing.to_string(), "/files/*path");
+        }
+        other => panic!("Expected a collision, got {:?}", other),
+    }
+
+    let request = route::new_route("/users/42").unwrap();
+    match r.get(&Method::Get, &request) {
+        MethodMatch::Found(x, params) => {
+            assert_eq!(*x, 1);
+            assert_eq!(params.get_str("id"), Some("42"));
+        }
+        _ => panic!("GET should have been found"),
+    }
+    match r.route(&Method::Delete, &request) {
+        MethodMatch::Found(x, _) => *x = 20,
+        _ => panic!("DELETE should have been found"),
+    }
+    assert!(matches!(
+        r.get(&Method::Delete, &request),
+        MethodMatch::Found(20, _)
+    ));
+
+    //HEAD falls back to GET
+    assert!(matches!(
+        r.get(&Method::Head, &request),
+        MethodMatch::Found(1, _)
+    ));
+
+    //the path exists but the method does not
+    assert_eq!(
+        r.get(&Method::Put, &request),
+        MethodMatch::MethodNotAllowed(vec![
+            Method::Get,
+            Method::Delete,
+            Method::Head,
+            Method::Options
+        ])
+    );
+    assert_eq!(
+        method::allow_header(&[Method::Get, Method::Head, Method::Options]),
+        "GET, HEAD, OPTIONS"
+    );
+
+    //OPTIONS is answered with the allowed methods
+    match r.get(&Method::Options, &request) {
+        MethodMatch::Options(allowed, params) => {
+            assert_eq!(
+                allowed,
+                vec![Method::Get, Method::Delete, Method::Head, Method::Options]
+            );
+            assert_eq!(params.get_str("id"), Some("42"));
+        }
+        _ => panic!("OPTIONS should have been answered"),
+    }
+
+    //the specific route only has POST, so GET falls through to the parameter route
+    let admin_request = route::new_route("/users/admin").unwrap();
+    assert!(matches!(
+        r.get(&Method::Post, &admin_request),
+        MethodMatch::Found(3, _)
+    ));
+    assert!(matches!(
+        r.get(&Method::Get, &admin_request),
+        MethodMatch::Found(1, _)
+    ));
+
+    //the allowed methods come from all routes that match, not only the most specific one
+    let mut overlapping: method::MethodRouter<u32> = method::new_method_router();
+    overlapping.add(Method::Get, &users, 1).unwrap();
+    overlapping
+        .add(Method::Delete, &route::new_route("/users/me").unwrap(), 2)
+        .unwrap();
+    let me = route::new_route("/users/me").unwrap();
+    assert!(matches!(
+        overlapping.get(&Method::Get, &me),
+        MethodMatch::Found(1, _)
+    ));
+    let allowed = vec![Method::Delete, Method::Get, Method::Head, Method::Options];
+    assert_eq!(
+        overlapping.get(&Method::Put, &me),
+        MethodMatch::MethodNotAllowed(allowed.clone())
+    );
+    match overlapping.route(&Method::Options, &me) {
+        MethodMatch::Options(methods, _) => assert_eq!(methods, allowed),
+        _ => panic!("OPTIONS should have been answered"),
+    }
+
+    //an explicitly added OPTIONS item is used
+    r.add(Method::Options, &admin, 5).unwrap();
+    assert!(matches!(
+        r.route(&Method::Options, &admin_request),
+        MethodMatch::Found(5, _)
+    ));
+
+    assert_eq!(
+        r.get(&Method::Get, &route::new_route("/other").unwrap()),
+        MethodMatch::NotFound
+    );
+
+    //removing the last method removes the path
+    assert_eq!(r.remove(&Method::Post, &admin), Some(3));
+    assert_eq!(r.remove(&Method::Options, &admin), Some(5));
+    assert_eq!(r.remove(&Method::Options, &admin), None);
+    assert!(matches!(
+        r.get(&Method::Post, &admin_request),
+        MethodMatch::MethodNotAllowed(_)
+    ));
+
+    assert_eq!("PATCH".parse::<Method>().unwrap(), Method::Patch);
+    assert_eq!(
+        "PURGE".parse::<Method>().unwrap(),
+        Method::Other("PURGE".to_owned())
+    );
+    assert!("GET /".parse::<Method>().is_err());
+    assert!("".parse::<Method>().is_err());
+}
+
+#[test]
+fn test_typed_params() {
+    use crate::params::{FromParams, ParamError, Params};
+
+    #[derive(Debug, PartialEq)]
+    struct Item {
+        kind: String,
+        id: u64,
+    }
+
+    impl FromParams for Item {
+        fn from_params(params: &Params) -> Result<Self, ParamError> {
+            Ok(Item {
+                kind: params.get("kind")?,
+                id: params.get(":id")?,
+            })
+        }
+    }
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/items/:kind/:id").unwrap(), 0)
+        .unwrap();
+
+    let (_, params) = r.get(&route::new_route("/items/book/42").unwrap()).unwrap();
+    assert_eq!(params.len(), 2);
+    assert_eq!(params.get::<u64>("id"), Ok(42));
+    assert_eq!(params.get::<u64>(":id"), Ok(42));
+    assert_eq!(params.get::<String>("kind"), Ok("book".to_owned()));
+    assert_eq!(params.get_str(":kind"), Some("book"));
+    assert!(params.contains("kind"));
+    assert!(!params.contains("other"));
+    assert_eq!(
+        params.parse::<Item>(),
+        Ok(Item {
+            kind: "book".to_owned(),
+            id: 42
+        })
+    );
+
+    assert_eq!(
+        params.get::<u64>("missing"),
+        Err(ParamError::Missing("missing".to_owned()))
+    );
+    assert_eq!(
+        params.get::<u8>("kind"),
+        Err(ParamError::Invalid(
+            "kind".to_owned(),
+            "book".to_owned(),
+            "invalid digit found in string".to_owned()
+        ))
+    );
+
+    let (_, params) = r.get(&route::new_route("/items/book/x").unwrap()).unwrap();
+    match params.parse::<Item>() {
+        Err(ParamError::Invalid(name, value, _)) => {
+            assert_eq!(name, "id");
+            assert_eq!(value, "x");
+        }
+        other => panic!("Expected an invalid parameter, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parameter_constraints() {
+    use crate::constraint::Constraint;
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/users/:id<u64>").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/users/:name").unwrap(), 2)
+        .unwrap();
+    r.add_route(&route::new_route("/files/:name<[a-z]+>").unwrap(), 3)
+        .unwrap();
+    r.add_route(
+        &route::new_route("/files/:other<[0-9]{2,3}\\.txt>").unwrap(),
+        4,
+    )
+    .unwrap();
+
+    //the constrained parameter is tried first, the plain one catches the rest
+    let (x, params) = r.get(&route::new_route("/users/42").unwrap()).unwrap();
+    assert_eq!(*x, 1);
+    assert_eq!(params.get::<u64>("id"), Ok(42));
+    let (x, params) = r.get(&route::new_route("/users/alice").unwrap()).unwrap();
+    assert_eq!(*x, 2);
+    assert_eq!(params.get_str("name"), Some("alice"));
+    assert!(!params.contains("id"));
+    let (x, _) = r.get(&route::new_route("/users/-1").unwrap()).unwrap();
+    assert_eq!(*x, 2);
+
+    let (x, params) = r.get(&route::new_route("/files/abc").unwrap()).unwrap();
+    assert_eq!(*x, 3);
+    assert_eq!(params.get_str("name"), Some("abc"));
+    let (x, params) = r.get(&route::new_route("/files/123.txt").unwrap()).unwrap();
+    assert_eq!(*x, 4);
+    assert_eq!(params.get_str("other"), Some("123.txt"));
+    assert!(r
+        .get(&route::new_route("/files/1234.txt").unwrap())
+        .is_none());
+    assert!(r.get(&route::new_route("/files/ABC").unwrap()).is_none());
+
+    //same constraint with a different name still collides, the same parameter is shared
+    assert!(r
+        .add_route(&route::new_route("/users/:uid<u64>").unwrap(), 0)
+        .is_err());
+    r.add_route(&route::new_route("/users/:id<u64>/posts").unwrap(), 5)
+        .unwrap();
+    assert_eq!(
+        r.remove_route(&route::new_route("/users/:id<u64>").unwrap()),
+        Some(1)
+    );
+    let (x, _) = r.get(&route::new_route("/users/42").unwrap()).unwrap();
+    assert_eq!(*x, 2);
+
+    //types are tried before patterns and smaller types before larger ones
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/n/:x<[0-9a-f]+>").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/n/:x<i64>").unwrap(), 2)
+        .unwrap();
+    r.add_route(&route::new_route("/n/:x<u64>").unwrap(), 3)
+        .unwrap();
+    r.add_route(&route::new_route("/n/:x<u8>").unwrap(), 4)
+        .unwrap();
+    let item = |path: &str| r.get(&route::new_route(path).unwrap()).map(|(x, _)| *x);
+    assert_eq!(item("/n/7"), Some(4));
+    assert_eq!(item("/n/300"), Some(3));
+    assert_eq!(item("/n/-3"), Some(2));
+    assert_eq!(item("/n/ff"), Some(1));
+
+    //the strict router does not allow them next to each other
+    let mut r: router::Router<u32> = router::new_strict_router();
+    r.add_route(&route::new_route("/users/:id<u64>").unwrap(), 1)
+        .unwrap();
+    assert!(r
+        .add_route(&route::new_route("/users/:name").unwrap(), 2)
+        .is_err());
+
+    //a '?' in a pattern is a quantifier, patterns have no query
+    let mut r: router::Router<u32> = router::new_router();
+    let pattern = route::new_pattern("/a/:x<colou?r>").unwrap();
+    assert_eq!(pattern.path, ["", "a", ":x<colou?r>"]);
+    assert_eq!(pattern.query, None);
+    r.add_route(&pattern, 1).unwrap();
+    let requests = [
+        ("/a/color", "color"),
+        ("/a/colour", "colour"),
+        ("/a/color?x=1", "color"),
+    ];
+    for (path, value) in requests {
+        let (x, params) = r.get(&route::new_route(path).unwrap()).unwrap();
+        assert_eq!(*x, 1);
+        assert_eq!(params.get_str("x"), Some(value));
+    }
+    assert!(r.get(&route::new_route("/a/colr").unwrap()).is_none());
+    assert!(r.contains_route(&pattern));
+
+    //malformed constraints are rejected when parsing the route
+    assert!(route::new_pattern("/users/:id<u64").is_err());
+    assert!(route::new_pattern("/users/:id<>").is_err());
+    assert!(route::new_pattern("/users/:id<[a-z>").is_err());
+    assert!(route::new_pattern("/users/:id<+>").is_err());
+    assert!(route::new_pattern("/users/:id<a{3,1}>").is_err());
+
+    let c = Constraint::new("[^-][a-z0-9-]*").unwrap();
+    assert!(c.matches("abc-1"));
+    assert!(!c.matches("-abc"));
+    assert!(!c.matches(""));
+    let c = Constraint::new("v?[0-9]+").unwrap();
+    assert!(c.matches("v1"));
+    assert!(c.matches("12"));
+    assert!(!c.matches("v"));
+    let c = Constraint::new("bool").unwrap();
+    assert!(c.matches("true"));
+    assert!(!c.matches("yes"));
+    assert_eq!(c.as_str(), "bool");
+
+    let c = Constraint::new("a{2,3}b?.{0,2}").unwrap();
+    for (value, expected) in &[
+        ("aa", true),
+        ("aaab", true),
+        ("aaabxy", true),
+        ("aaaaa", true),
+        ("a", false),
+        ("aabxyz", false),
+    ] {
+        assert_eq!(c.matches(value), *expected, "{}", value);
+    }
+
+    //values that almost match do not make the matcher try all ways to split them up
+    let c = Constraint::new("a*a*a*a*a*a*a*a*b").unwrap();
+    let value = "a".repeat(20_000);
+    let start = std::time::Instant::now();
+    assert!(!c.matches(&value));
+    assert!(c.matches(&format!("{}b", value)));
+    assert!(
+        start.elapsed() < std::time::Duration::from_secs(1),
+        "took {:?}",
+        start.elapsed()
+    );
+}
+
+#[test]
+fn test_catch_all_capture() {
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/static/*path").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/files/:user/*").unwrap(), 2)
+        .unwrap();
+    r.add_route(&route::new_route("/static/*/meta").unwrap(), 3)
+        .unwrap();
+
+    let (x, params) = r
+        .get(&route::new_route("/static/css/site/main.css").unwrap())
+        .unwrap();
+    assert_eq!(*x, 1);
+    assert_eq!(params.get_str("path"), Some("css/site/main.css"));
+    assert_eq!(params.len(), 1);
+
+    //the wildcard needs at least one part to match
+    let (x, params) = r.get(&route::new_route("/static/a").unwrap()).unwrap();
+    assert_eq!(*x, 1);
+    assert_eq!(params.get_str("path"), Some("a"));
+    assert!(r.get(&route::new_route("/static").unwrap()).is_none());
+
+    //the rest of an unnamed wildcard is available as "*"
+    let (x, params) = r
+        .get(&route::new_route("/files/alice/docs/a.txt").unwrap())
+        .unwrap();
+    assert_eq!(*x, 2);
+    assert_eq!(params.get_str("user"), Some("alice"));
+    assert_eq!(params.get_str("*"), Some("docs/a.txt"));
+
+    //wildcards in the middle of a route do not capture anything
+    let (x, params) = r.get(&route::new_route("/static/a/meta").unwrap()).unwrap();
+    assert_eq!(*x, 3);
+    assert!(params.is_empty());
+
+    //a named and an unnamed wildcard at the end of the same route are the same route
+    assert!(r
+        .add_route(&route::new_route("/static/*").unwrap(), 4)
+        .is_err());
+    assert_eq!(
+        r.remove_route(&route::new_route("/static/*path").unwrap()),
+        Some(1)
+    );
+
+    assert!(route::new_pattern("/static/*path/more").is_err());
+}
+
+#[test]
+fn test_owned_routes() {
+    use crate::route::{Route, RouteBuf};
+    use std::convert::TryFrom;
+
+    let owned: RouteBuf = {
+        let input = String::from("/users/:id<u64>/*rest");
+        input.parse().unwrap()
+    };
+    assert_eq!(owned.to_string(), "/users/:id<u64>/*rest");
+    assert_eq!(owned.path, vec!["", "users", ":id<u64>", "*rest"]);
+
+    //the owned route can be moved to another thread
+    let owned = std::thread::spawn(move || owned.clone()).join().unwrap();
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&owned.as_route(), 1).unwrap();
+    let (x, params) = r.get(&route::new_route("/users/42/a/b").unwrap()).unwrap();
+    assert_eq!(*x, 1);
+    assert_eq!(params.get_str("rest"), Some("a/b"));
+
+    //conversions in both directions agree
+    let borrowed = route::new_route("/a/b/c").unwrap();
+    let converted = borrowed.to_route_buf();
+    assert_eq!(converted.as_route(), borrowed);
+    assert_eq!(RouteBuf::from(borrowed.clone()), converted);
+    assert_eq!(Route::from(&converted), borrowed);
+    assert_eq!(borrowed.to_string(), "/a/b/c");
+
+    assert_eq!(Route::try_from("/a/b/c").unwrap(), borrowed);
+    assert_eq!(RouteBuf::try_from("/a/b/c").unwrap(), converted);
+    assert_eq!(RouteBuf::try_from("/a/b/c".to_owned()).unwrap(), converted);
+    assert!(RouteBuf::try_from("a/b").is_err());
+    assert!(Route::try_from("/a/b/").is_err());
+    assert!("".parse::<RouteBuf>().is_err());
+}
+
+#[test]
+fn test_malformed_routes() {
+    use crate::route::MalformedRouteError;
+
+    let error = |p: &str| route::new_pattern(p).unwrap_err();
+
+    assert_eq!(error(""), MalformedRouteError::Empty);
+    assert_eq!(error("a/b"), MalformedRouteError::MissingLeadingSlash(0));
+    assert_eq!(error("/a/b/"), MalformedRouteError::TrailingSlash(4));
+    assert_eq!(error("/"), MalformedRouteError::TrailingSlash(0));
+    assert_eq!(error("/a//b"), MalformedRouteError::EmptySegment(3));
+    assert_eq!(
+        error("/a/:/b"),
+        MalformedRouteError::InvalidParameterName(3)
+    );
+    assert_eq!(
+        error("/a/:na-me"),
+        MalformedRouteError::InvalidParameterName(3)
+    );
+    assert_eq!(
+        error("/a/:<u64>"),
+        MalformedRouteError::InvalidParameterName(3)
+    );
+    assert_eq!(
+        error("/files/*re.st"),
+        MalformedRouteError::InvalidParameterName(7)
+    );
+    assert_eq!(
+        error("/files/*rest/more"),
+        MalformedRouteError::WildcardNotLast(7)
+    );
+    match error("/users/:id<u64") {
+        MalformedRouteError::InvalidConstraint(offset, _) => assert_eq!(offset, 10),
+        other => panic!("Expected an invalid constraint, got {:?}", other),
+    }
+    assert_eq!(error("/users/:id<[a-z>").offset(), 10);
+    assert_eq!(
+        error("/users/:id/posts/:id<u64>"),
+        MalformedRouteError::DuplicateParameterName(17)
+    );
+    assert_eq!(
+        error("/users/:rest/*rest"),
+        MalformedRouteError::DuplicateParameterName(13)
+    );
+
+    //unnamed wildcards can be anywhere
+    assert!(route::new_pattern("/a/*/b/*").is_ok());
+    assert!(route::new_pattern("/a/:param_1/*rest_2").is_ok());
+
+    //paths that are looked up only need slashes and escapes in order
+    assert_eq!(
+        route::new_route("/a//b").unwrap_err(),
+        MalformedRouteError::EmptySegment(3)
+    );
+    for path in &["/a/:/b", "/a/:na-me", "/files/*rest/more", "/users/:id<u64"] {
+        assert!(route::new_route(path).is_ok());
+    }
+    //routes that skipped new_pattern are checked when they are added
+    let mut r = router::new_router();
+    match r.add_route(&route::new_route("/files/*rest/more").unwrap(), 0) {
+        Err(router::AddRouteError::MalformedPattern(e)) => {
+            assert_eq!(e, MalformedRouteError::WildcardNotLast(7))
+        }
+        other => panic!("Expected a malformed pattern, got {:?}", other),
+    }
+    assert!(r.is_empty());
+
+    assert_eq!(
+        error("/a//b").to_string(),
+        "This route is malformed: it contains an empty part (at byte 3)"
+    );
+}
+
+#[test]
+fn test_checked_trees() {
+    use crate::route::Route;
+    use router::{Router, RouterError, Tree};
+
+    //a tree taken from a router is accepted again
+    let mut r: Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/a/:param<u64>/c").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/a/b/*rest").unwrap(), 2)
+        .unwrap();
+    let mut r = Router::from_tree(r.into_tree()).unwrap();
+    assert_eq!(*r.get(&route::new_route("/a/1/c").unwrap()).unwrap().0, 1);
+    assert_eq!(*r.get(&route::new_route("/a/b/x").unwrap()).unwrap().0, 2);
+    r.add_route(&route::new_route("/a/b").unwrap(), 3).unwrap();
+    assert!(matches!(r.tree(), Tree::Wildcard(_)));
+
+    let is_corrupt = |tree: Tree<u32>| match Router::from_tree(tree) {
+        Err(RouterError::CorruptTree(_)) => true,
+        Ok(_) => false,
+    };
+    let specific = |name: &str, children| Tree::Specific(name.to_owned(), children);
+
+    assert!(is_corrupt(Tree::Leaf(1, None, None)));
+    assert!(is_corrupt(specific("", vec![])));
+    assert!(is_corrupt(Tree::Wildcard(vec![Tree::Leaf(1, None, None)])));
+    //two leaves for the same route
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![specific(
+            "a",
+            vec![Tree::Leaf(1, None, None), Tree::Leaf(2, None, None)]
+        )]
+    )])));
+    //the same part twice
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![
+            specific("a", vec![Tree::Leaf(1, None, None)]),
+            specific("a", vec![Tree::Leaf(2, None, None)])
+        ]
+    )])));
+    //catch-all leaves only work below wildcards
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![specific(
+            "a",
+            vec![Tree::Leaf(1, Some("rest".to_owned()), None)]
+        )]
+    )])));
+    //invalid names
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![Tree::Parameter(
+            "param".to_owned(),
+            None,
+            vec![Tree::Leaf(1, None, None)]
+        )]
+    )])));
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![specific("a/b", vec![Tree::Leaf(1, None, None)])]
+    )])));
+    assert!(!is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![Tree::Wildcard(vec![Tree::Leaf(
+            1,
+            Some("rest".to_owned()),
+            None
+        )])]
+    )])));
+    //names are checked like new_pattern checks them, add_route could never build these
+    let parameter = |name: &str, children| Tree::Parameter(name.to_owned(), None, children);
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![parameter(":", vec![Tree::Leaf(1, None, None)])]
+    )])));
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![parameter(":x y", vec![Tree::Leaf(1, None, None)])]
+    )])));
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![specific(
+            "w",
+            vec![Tree::Wildcard(vec![Tree::Leaf(
+                1,
+                Some("a b".to_owned()),
+                None
+            )])]
+        )]
+    )])));
+    //the same name twice on one path
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![parameter(
+            ":q",
+            vec![specific(
+                "a",
+                vec![parameter(":q", vec![Tree::Leaf(1, None, None)])]
+            )]
+        )]
+    )])));
+    assert!(is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![parameter(
+            ":rest",
+            vec![Tree::Wildcard(vec![Tree::Leaf(
+                1,
+                Some("rest".to_owned()),
+                None
+            )])]
+        )]
+    )])));
+    //the same name on different paths is fine
+    assert!(!is_corrupt(Tree::Wildcard(vec![specific(
+        "",
+        vec![
+            specific("a", vec![parameter(":q", vec![Tree::Leaf(1, None, None)])]),
+            specific("b", vec![parameter(":q", vec![Tree::Leaf(2, None, None)])])
+        ]
+    )])));
+
+    //routes without any parts can not be added
+    assert!(matches!(
+        r.add_route(
+            &Route {
+                path: vec![],
+                query: None,
+                fragment: None,
+            },
+            0
+        ),
+        Err(router::AddRouteError::EmptyRoute)
+    ));
+    assert!(r
+        .get(&Route {
+            path: vec![],
+            query: None,
+            fragment: None,
+        })
+        .is_none());
+}
+
+#[test]
+fn test_url_for() {
+    use router::UrlError;
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_named_route("user", &route::new_route("/users/:id<u64>").unwrap(), 1)
+        .unwrap();
+    r.add_named_route("file", &route::new_route("/files/:user/*path").unwrap(), 2)
+        .unwrap();
+    r.add_named_route("any", &route::new_route("/any/*/x/*").unwrap(), 3)
+        .unwrap();
+    r.add_named_route("index", &route::new_route("/index").unwrap(), 4)
+        .unwrap();
+
+    assert_eq!(
+        r.url_for("user", &[("id", "42")]),
+        Ok("/users/42".to_owned())
+    );
+    assert_eq!(
+        r.url_for("user", &[(":id", "42")]),
+        Ok("/users/42".to_owned())
+    );
+    assert_eq!(r.url_for("index", &[]), Ok("/index".to_owned()));
+    assert_eq!(
+        r.url_for("file", &[("user", "jane doe"), ("path", "docs/a&b.txt")]),
+        Ok("/files/jane%20doe/docs/a%26b.txt".to_owned())
+    );
+    assert_eq!(
+        r.url_for("any", &[("*", "a/b")]),
+        Ok("/any/a%2Fb/x/a/b".to_owned())
+    );
+
+    //the generated url routes back to the same item
+    let url = r
+        .url_for("file", &[("user", "jane"), ("path", "docs/a.txt")])
+        .unwrap();
+    let (x, params) = r.get(&route::new_route(&url).unwrap()).unwrap();
+    assert_eq!(*x, 2);
+    assert_eq!(params.get_str("path"), Some("docs/a.txt"));
+
+    assert_eq!(
+        r.url_for("nothing", &[]),
+        Err(UrlError::UnknownRoute("nothing".to_owned()))
+    );
+    assert_eq!(
+        r.url_for("user", &[]),
+        Err(UrlError::MissingParameter("id".to_owned()))
+    );
+    assert_eq!(
+        r.url_for("user", &[("id", "42"), ("other", "x")]),
+        Err(UrlError::ExtraParameter("other".to_owned()))
+    );
+    assert_eq!(
+        r.url_for("user", &[("id", "alice")]),
+        Err(UrlError::InvalidParameter(
+            "id".to_owned(),
+            "alice".to_owned()
+        ))
+    );
+    //dot segments would be resolved away and lead to another route
+    assert_eq!(
+        r.url_for("file", &[("user", ".."), ("path", "a.txt")]),
+        Err(UrlError::InvalidParameter(
+            "user".to_owned(),
+            "..".to_owned()
+        ))
+    );
+    assert_eq!(
+        r.url_for("file", &[("user", "."), ("path", "a.txt")]),
+        Err(UrlError::InvalidParameter(
+            "user".to_owned(),
+            ".".to_owned()
+        ))
+    );
+    assert_eq!(
+        r.url_for("file", &[("user", "jane"), ("path", "css/../../secret")]),
+        Err(UrlError::InvalidParameter(
+            "path".to_owned(),
+            "css/../../secret".to_owned()
+        ))
+    );
+    assert!(r
+        .url_for("file", &[("user", "jane"), ("path", "./a.txt")])
+        .is_err());
+    assert!(r.url_for("any", &[("*", "..")]).is_err());
+    //dots inside a part are fine
+    assert_eq!(
+        r.url_for("file", &[("user", "..jane"), ("path", "a/.b/c..")]),
+        Ok("/files/..jane/a/.b/c..".to_owned())
+    );
+    assert_eq!(
+        r.url_for("file", &[("user", "jane"), ("path", "a//b")]),
+        Err(UrlError::InvalidParameter(
+            "path".to_owned(),
+            "a//b".to_owned()
+        ))
+    );
+
+    //names are unique and are forgotten with their route
+    assert!(matches!(
+        r.add_named_route("user", &route::new_route("/other").unwrap(), 5),
+        Err(router::AddRouteError::NameExists(_))
+    ));
+    assert_eq!(
+        r.remove_route(&route::new_route("/users/:id<u64>").unwrap()),
+        Some(1)
+    );
+    assert_eq!(
+        r.url_for("user", &[("id", "42")]),
+        Err(UrlError::UnknownRoute("user".to_owned()))
+    );
+    r.add_named_route("user", &route::new_route("/u/:id").unwrap(), 6)
+        .unwrap();
+    assert_eq!(r.url_for("user", &[("id", "42")]), Ok("/u/42".to_owned()));
+}
+
+#[test]
+fn test_query_and_fragment() {
+    use crate::params::Query;
+    use crate::route::RouteBuf;
+
+    let request = route::new_route("/search/books?q=rust&tag=a&tag=b&flag#results").unwrap();
+    assert_eq!(request.path, vec!["", "search", "books"]);
+    assert_eq!(request.query, Some("q=rust&tag=a&tag=b&flag"));
+    assert_eq!(request.fragment, Some("results"));
+    assert_eq!(
+        request.to_string(),
+        "/search/books?q=rust&tag=a&tag=b&flag#results"
+    );
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/search/:kind").unwrap(), 1)
+        .unwrap();
+
+    //the query and the fragment are ignored for matching
+    let (x, params) = r.get(&request).unwrap();
+    assert_eq!(*x, 1);
+    assert_eq!(params.get_str("kind"), Some("books"));
+    assert!(!params.contains("q"));
+    let query = params.query();
+    assert_eq!(query.get("q"), Some("rust"));
+    assert_eq!(query.get_all("tag").collect::<Vec<_>>(), vec!["a", "b"]);
+    assert_eq!(query.get("flag"), Some(""));
+    assert_eq!(query.get("missing"), None);
+    assert_eq!(query.len(), 4);
+
+    let (_, params) = r
+        .route(&route::new_route("/search/films#top").unwrap())
+        .unwrap();
+    assert!(params.query().is_empty());
+
+    //the path still has to be well formed
+    assert!(route::new_route("/search/?q=x").is_err());
+    assert!(route::new_route("?q=x").is_err());
+    let r = route::new_route("/a?b/c#d?e").unwrap();
+    assert_eq!(r.path, vec!["", "a"]);
+    assert_eq!(r.query, Some("b/c"));
+    assert_eq!(r.fragment, Some("d?e"));
+
+    let owned: RouteBuf = "/a?x=1#f".parse().unwrap();
+    assert_eq!(owned.query, Some("x=1".to_owned()));
+    assert_eq!(owned.to_string(), "/a?x=1#f");
+
+    assert_eq!(
+        Query::parse("a=1&&b=2=3")
+            .iter()
+            .collect::<Vec<(&str, &str)>>(),
+        vec![("a", "1"), ("b", "2=3")]
+    );
+}
+
+#[test]
+fn test_percent_decoding() {
+    use crate::route::MalformedRouteError;
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/files/:name").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/my%20docs/*path").unwrap(), 2)
+        .unwrap();
+    r.add_route(&route::new_route("/tags/:tag<[a-z ]+>").unwrap(), 3)
+        .unwrap();
+
+    //parameters are decoded after splitting, so an encoded slash stays in its part
+    let (x, params) = r
+        .get(&route::new_route("/files/my%20doc%2Fv2").unwrap())
+        .unwrap();
+    assert_eq!(*x, 1);
+    assert_eq!(params.get_str("name"), Some("my doc/v2"));
+    assert!(r
+        .get(&route::new_route("/files/my%20doc/v2").unwrap())
+        .is_none());
+
+    //specific parts are compared decoded
+    let (x, params) = r
+        .get(&route::new_route("/my docs/a%C3%A4/b%3Fc").unwrap())
+        .unwrap();
+    assert_eq!(*x, 2);
+    assert_eq!(params.get_str("path"), Some("aä/b?c"));
+
+    //an encoded slash in the rest stays escaped, the parts are decoded one by one
+    for request in &[
+        "/my docs/a%2Fb/c",
+        "/my docs/a/b/c",
+        "/my docs/100%25/%2E%2E/x",
+    ] {
+        let from_route = r.get(&route::new_route(request).unwrap()).unwrap().1;
+        let from_str = r.get_str(request).unwrap().unwrap().1;
+        assert_eq!(from_route, from_str, "{}", request);
+    }
+    let rest = |request: &str| {
+        let (_, params) = r.get(&route::new_route(request).unwrap()).unwrap();
+        let parts = params
+            .rest()
+            .map(|(name, parts)| (name.to_owned(), parts.to_vec()));
+        (params.get_str("path").unwrap().to_owned(), parts.unwrap())
+    };
+    let (joined, (name, parts)) = rest("/my docs/a%2Fb/c");
+    assert_eq!(joined, "a%2Fb/c");
+    assert_eq!(
+        (name.as_str(), parts),
+        ("path", vec!["a/b".to_owned(), "c".to_owned()])
+    );
+    assert_eq!(rest("/my docs/a/b/c").0, "a/b/c");
+    assert_eq!(rest("/my docs/a/b/c").1 .1, ["a", "b", "c"]);
+    //decoding never turns a part into a dot segment of the joined value
+    let (joined, (_, parts)) = rest("/my docs/100%25/%2E%2E/x");
+    assert_eq!(joined, "100%25/%2E%2E/x");
+    assert_eq!(parts, ["100%", "..", "x"]);
+    let (x, _) = r.get(&route::new_route("/my%20docs/x").unwrap()).unwrap();
+    assert_eq!(*x, 2);
+    assert!(r
+        .add_route(&route::new_route("/my docs/*").unwrap(), 4)
+        .is_err());
+
+    //constraints are checked against the decoded value
+    let (x, params) = r.get(&route::new_route("/tags/a%20b").unwrap()).unwrap();
+    assert_eq!(*x, 3);
+    assert_eq!(params.get_str("tag"), Some("a b"));
+
+    //the query is decoded too
+    let (_, params) = r
+        .get(&route::new_route("/files/x?q=a+b%26c&k%3D=v").unwrap())
+        .unwrap();
+    assert_eq!(params.query().get("q"), Some("a b&c"));
+    assert_eq!(params.query().get("k="), Some("v"));
+
+    assert_eq!(
+        route::new_route("/files/a%2").unwrap_err(),
+        MalformedRouteError::InvalidEscape(8)
+    );
+    assert_eq!(
+        route::new_route("/files/a%zz").unwrap_err(),
+        MalformedRouteError::InvalidEscape(8)
+    );
+    assert_eq!(
+        route::new_route("/files/ok/a%C3%28").unwrap_err(),
+        MalformedRouteError::InvalidUtf8(11)
+    );
+
+    //broken escapes in the query do not make the path unusable
+    let (_, params) = r
+        .get(&route::new_route("/files/x?a=1&b=%FF&q=100%").unwrap())
+        .unwrap();
+    assert_eq!(params.query().get("b"), Some("%FF"));
+    assert_eq!(params.query().get("q"), Some("100%"));
+    assert!(r.get_str("/files/x?q=100%").unwrap().is_some());
+}
+
+#[test]
+fn test_path_normalization() {
+    use router::{Normalization, PathMatch};
+
+    assert_eq!(route::normalize("/a/b/"), "/a/b");
+    assert_eq!(route::normalize("//a///b"), "/a/b");
+    assert_eq!(route::normalize("/a/./b/../c"), "/a/c");
+    assert_eq!(route::normalize("/a/b/c/./../../g"), "/a/g");
+    assert_eq!(route::normalize("/../a/.."), "/");
+    assert_eq!(route::normalize("/a/..b/.c"), "/a/..b/.c");
+    assert_eq!(route::normalize("/a//b/?q=/x/#/f/"), "/a/b?q=/x/#/f/");
+    assert_eq!(route::normalize("a/b/"), "a/b/");
+    //escaped dots are dots
+    assert_eq!(route::normalize("/a/%2E%2E/b"), "/b");
+    assert_eq!(route::normalize("/a/%2e./%2E/b"), "/b");
+    assert_eq!(route::normalize("/a/file%2Etxt"), "/a/file.txt");
+    assert_eq!(route::normalize("/a/%2F%2E"), "/a/%2F.");
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/a/:param").unwrap(), 1)
+        .unwrap();
+
+    //strict paths have to be canonical already
+    assert_eq!(r.normalization(), Normalization::Strict);
+    assert!(matches!(r.get_path("/a/b"), Ok(PathMatch::Found(1, _))));
+    assert!(r.get_path("/a/b/").is_err());
+    assert!(r.get_path("/a//b").is_err());
+
+    r.set_normalization(Normalization::IgnoreTrailingSlash);
+    //the escaped ".." is resolved before the parameter could capture it
+    assert!(matches!(
+        r.get_path("/a/%2E%2E/a/b"),
+        Ok(PathMatch::Found(1, params)) if params.get_str("param") == Some("b")
+    ));
+    assert!(matches!(r.get_path("/a/b/%2e%2E"), Ok(PathMatch::NotFound)));
+    match r.route_path("/a/b/?x=1") {
+        Ok(PathMatch::Found(x, params)) => {
+            assert_eq!(*x, 1);
+            assert_eq!(params.get_str("param"), Some("b"));
+            assert_eq!(params.query().get("x"), Some("1"));
+        }
+        other => panic!("Expected a match, got {:?}", other),
+    }
+    assert!(matches!(
+        r.get_path("//a/c/./d/.."),
+        Ok(PathMatch::Found(1, _))
+    ));
+    assert_eq!(r.get_path("/a/b/c/"), Ok(PathMatch::NotFound));
+    assert_eq!(r.get_path("/.."), Ok(PathMatch::NotFound));
+    assert!(r.get_path("a/b").is_err());
+
+    r.set_normalization(Normalization::RedirectToCanonical);
+    assert_eq!(
+        r.get_path("/a//b/?x=1"),
+        Ok(PathMatch::Redirect("/a/b?x=1".to_owned()))
+    );
+    assert_eq!(
+        r.route_path("/x/../a/b"),
+        Ok(PathMatch::Redirect("/a/b".to_owned()))
+    );
+    //canonical paths are served directly, paths that do not match anything are not redirected
+    assert!(matches!(r.route_path("/a/b"), Ok(PathMatch::Found(1, _))));
+    assert_eq!(r.get_path("/a/b/c/"), Ok(PathMatch::NotFound));
+}
+
+#[test]
+fn test_case_insensitive_matching() {
+    use crate::case::CaseSensitivity;
+
+    let mut r: router::Router<u32> = router::new_router();
+    assert_eq!(r.case_sensitivity(), CaseSensitivity::Sensitive);
+    r.add_route(&route::new_route("/api/users/:name").unwrap(), 1)
+        .unwrap();
+    assert!(r
+        .get(&route::new_route("/API/Users/Bob").unwrap())
+        .is_none());
+
+    r.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    match r.get(&route::new_route("/API/Users/Bob").unwrap()) {
+        Some((x, params)) => {
+            assert_eq!(*x, 1);
+            //captured values keep their case
+            assert_eq!(params.get_str("name"), Some("Bob"));
+        }
+        None => panic!("Expected a match"),
+    }
+    //percent-escapes are decoded before comparing
+    assert!(r
+        .get(&route::new_route("/%41pi/users/bob").unwrap())
+        .is_some());
+
+    //routes that only differ in case are the same route now
+    assert!(matches!(
+        r.add_route(&route::new_route("/Api/USERS/:name").unwrap(), 2),
+        Err(router::AddRouteError::Collision(c)) if c.kind == router::CollisionKind::RouteExists
+    ));
+    let mut strict: router::Router<u32> = router::new_strict_router();
+    strict.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    strict
+        .add_route(&route::new_route("/a/b").unwrap(), 1)
+        .unwrap();
+    assert!(matches!(
+        strict.add_route(&route::new_route("/A/B").unwrap(), 2),
+        Err(router::AddRouteError::Collision(c)) if c.kind == router::CollisionKind::RouteExists
+    ));
+
+    //ASCII folding does not cover other letters
+    r.add_route(&route::new_route("/straße").unwrap(), 3)
+        .unwrap();
+    assert!(r.get(&route::new_route("/STRASSE").unwrap()).is_none());
+    r.set_case_sensitivity(CaseSensitivity::UnicodeInsensitive);
+    assert!(matches!(
+        r.get(&route::new_route("/STRASSE").unwrap()),
+        Some((3, _))
+    ));
+    assert!(r.get(&route::new_route("/äpi/users/x").unwrap()).is_none());
+
+    //a route can override the setting of the router in both directions
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route_with_case(
+        &route::new_route("/legacy/:id").unwrap(),
+        1,
+        CaseSensitivity::AsciiInsensitive,
+    )
+    .unwrap();
+    r.add_route(&route::new_route("/modern/:id").unwrap(), 2)
+        .unwrap();
+    assert!(matches!(
+        r.get(&route::new_route("/LEGACY/7").unwrap()),
+        Some((1, _))
+    ));
+    assert!(r.get(&route::new_route("/MODERN/7").unwrap()).is_none());
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    r.add_route_with_case(
+        &route::new_route("/exact").unwrap(),
+        1,
+        CaseSensitivity::Sensitive,
+    )
+    .unwrap();
+    r.add_route(&route::new_route("/loose").unwrap(), 2)
+        .unwrap();
+    assert!(r.get(&route::new_route("/EXACT").unwrap()).is_none());
+    assert!(matches!(
+        r.get(&route::new_route("/exact").unwrap()),
+        Some((1, _))
+    ));
+    assert!(matches!(
+        r.get(&route::new_route("/LOOSE").unwrap()),
+        Some((2, _))
+    ));
+
+    //an exact match is preferred over one that only matches when ignoring case
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/a/x").unwrap(), 1).unwrap();
+    r.add_route(&route::new_route("/A/y").unwrap(), 2).unwrap();
+    r.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    assert!(matches!(
+        r.get(&route::new_route("/A/x").unwrap()),
+        Some((1, _))
+    ));
+    assert!(matches!(
+        r.get(&route::new_route("/a/Y").unwrap()),
+        Some((2, _))
+    ));
+
+    //a route that went into a part with another case can be removed with its own pattern
+    let mut r: router::Router<u32> = router::new_router();
+    r.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    r.add_route(&route::new_route("/API/x").unwrap(), 1)
+        .unwrap();
+    r.add_named_route("y", &route::new_route("/api/y").unwrap(), 2)
+        .unwrap();
+    r.add_route_with_case(
+        &route::new_route("/API/Z").unwrap(),
+        3,
+        CaseSensitivity::Sensitive,
+    )
+    .unwrap();
+    assert!(r.contains_route(&route::new_route("/api/y").unwrap()));
+    assert!(r.contains_route(&route::new_route("/API/Y").unwrap()));
+    //the route only matches its own spelling, so its pattern has to be given that way
+    assert!(!r.contains_route(&route::new_route("/api/z").unwrap()));
+    assert_eq!(r.remove_route(&route::new_route("/api/z").unwrap()), None);
+    assert_eq!(
+        r.remove_route(&route::new_route("/api/y").unwrap()),
+        Some(2)
+    );
+    assert!(r.url_for("y", &[]).is_err());
+    assert!(!r.contains_route(&route::new_route("/api/y").unwrap()));
+    assert_eq!(
+        r.remove_route(&route::new_route("/api/x").unwrap()),
+        Some(1)
+    );
+    assert_eq!(
+        r.remove_route(&route::new_route("/API/Z").unwrap()),
+        Some(3)
+    );
+    assert!(r.is_empty());
+    assert_eq!(r.tree().to_string(), "*");
+}
+
+#[test]
+fn test_case_insensitive_siblings() {
+    use crate::case::CaseSensitivity;
+
+    //parts that only differ in case are tried together, wherever they are among their siblings
+    let mut r: router::Router<usize> = router::new_router();
+    for i in 0..50 {
+        let pattern = format!("/{}item{}", if i % 2 == 0 { "A" } else { "z" }, i);
+        r.add_route(&route::new_route(&pattern).unwrap(), i)
+            .unwrap();
+    }
+    r.add_route(&route::new_route("/aitem0/x").unwrap(), 100)
+        .unwrap();
+    r.add_route(&route::new_route("/AITEM0/y").unwrap(), 101)
+        .unwrap();
+    r.add_route(&route::new_route("/straße").unwrap(), 102)
+        .unwrap();
+    r.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    for i in 0..50 {
+        let path = format!("/{}ITEM{}", if i % 2 == 0 { "a" } else { "Z" }, i);
+        assert!(matches!(
+            r.get(&route::new_route(&path).unwrap()),
+            Some((x, _)) if *x == i
+        ));
+    }
+    assert!(matches!(
+        r.get(&route::new_route("/AItem0/X").unwrap()),
+        Some((100, _))
+    ));
+    assert!(matches!(
+        r.get(&route::new_route("/aitem0/Y").unwrap()),
+        Some((101, _))
+    ));
+    assert!(r.get(&route::new_route("/STRASSE").unwrap()).is_none());
+    r.set_case_sensitivity(CaseSensitivity::UnicodeInsensitive);
+    assert!(matches!(
+        r.get(&route::new_route("/Strasse").unwrap()),
+        Some((102, _))
+    ));
+    assert!(matches!(
+        r.get(&route::new_route("/%41ITEM2").unwrap()),
+        Some((2, _))
+    ));
+}
+
+#[test]
+fn test_shared_router() {
+    use crate::shared::SharedRouter;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_send_sync<S: Send + Sync>() {}
+    assert_send_sync::<SharedRouter<u32>>();
+
+    let shared = Arc::new(SharedRouter::new(router::new_router::<u32>()));
+    shared
+        .add_route(&route::new_route("/a/:id").unwrap(), 0)
+        .unwrap();
+
+    //failed changes are not applied and leave the router as it was
+    assert!(shared
+        .update(|r| {
+            r.add_route(&route::new_route("/b").unwrap(), 1)?;
+            r.add_route(&route::new_route("/a/:id").unwrap(), 2)
+        })
+        .is_err());
+    assert!(shared.get(&route::new_route("/b").unwrap()).is_none());
+
+    //snapshots do not see later changes
+    let before = shared.snapshot();
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                let request = route::new_route("/a/7").unwrap();
+                for _ in 0..1000 {
+                    let (item, params) = shared.get(&request).unwrap();
+                    assert_eq!(item, 0);
+                    assert_eq!(params.get::<u32>("id"), Ok(7));
+                }
+            })
+        })
+        .collect();
+    for i in 0..100 {
+        let path = format!("/n/{}", i);
+        shared
+            .add_route(&route::new_route(&path).unwrap(), i)
+            .unwrap();
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert!(before.get(&route::new_route("/n/99").unwrap()).is_none());
+    assert!(matches!(
+        shared.get(&route::new_route("/n/99").unwrap()),
+        Some((99, _))
+    ));
+
+    assert_eq!(
+        shared.remove_route(&route::new_route("/n/5").unwrap()),
+        Some(5)
+    );
+    assert_eq!(
+        shared.remove_route(&route::new_route("/n/5").unwrap()),
+        None
+    );
+    assert!(shared.get(&route::new_route("/n/5").unwrap()).is_none());
+}
+
+#[test]
+fn test_shared_router_writes_under_read_load() {
+    use crate::shared::SharedRouter;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    let shared = Arc::new(SharedRouter::new(router::new_router::<usize>()));
+    shared
+        .add_route(&route::new_route("/a/:id").unwrap(), 0)
+        .unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let lookups = Arc::new(AtomicUsize::new(0));
+    //readers keep looking up routes until the writers are done
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let (shared, stop, lookups) = (shared.clone(), stop.clone(), lookups.clone());
+            thread::spawn(move || {
+                let request = route::new_route("/a/7").unwrap();
+                while !stop.load(Ordering::SeqCst) {
+                    assert!(shared.get(&request).is_some());
+                    lookups.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect();
+    while lookups.load(Ordering::SeqCst) == 0 {
+        thread::yield_now();
+    }
+
+    let (done, finished) = mpsc::channel();
+    let writers: Vec<_> = (0..2)
+        .map(|writer| {
+            let (shared, done) = (shared.clone(), done.clone());
+            thread::spawn(move || {
+                for i in 0..200 {
+                    let path = format!("/w{}/{}", writer, i);
+                    shared
+                        .add_route(&route::new_route(&path).unwrap(), i)
+                        .unwrap();
+                }
+                done.send(writer).unwrap();
+            })
+        })
+        .collect();
+    for _ in 0..writers.len() {
+        let finished = finished.recv_timeout(Duration::from_secs(30));
+        assert!(
+            finished.is_ok(),
+            "a writer did not finish while reads went on"
+        );
+    }
+    stop.store(true, Ordering::SeqCst);
+    for thread in writers.into_iter().chain(readers) {
+        thread.join().unwrap();
+    }
+    assert_eq!(shared.snapshot().len(), 401);
+}
+
+#[test]
+fn test_many_siblings() {
+    use crate::router::Tree;
+
+    let mut r: router::Router<usize> = router::new_router();
+    //added out of order, with escaped names and parameters in between
+    for i in (0..500).map(|i| (i * 7) % 500) {
+        r.add_route(&route::new_route(&format!("/r{}/x", i)).unwrap(), i)
+            .unwrap();
+    }
+    r.add_route(&route::new_route("/:param/x").unwrap(), 500)
+        .unwrap();
+    r.add_route(&route::new_route("/a%20b/x").unwrap(), 501)
+        .unwrap();
+    for i in 0..500 {
+        let path = format!("/r{}/x", i);
+        let request = route::new_route(&path).unwrap();
+        assert_eq!(r.get(&request).map(|(x, _)| *x), Some(i));
+    }
+    assert!(matches!(
+        r.get(&route::new_route("/a b/x").unwrap()),
+        Some((501, _))
+    ));
+    assert!(matches!(
+        r.get(&route::new_route("/r500/x").unwrap()),
+        Some((500, _))
+    ));
+    assert!(matches!(
+        r.add_route(&route::new_route("/r%31/x").unwrap(), 0),
+        Err(router::AddRouteError::Collision(c)) if c.kind == router::CollisionKind::RouteExists
+    ));
+
+    //trees built by hand do not have to keep any order
+    let leaf = |item| vec![Tree::Leaf(item, None, None)];
+    let tree = Tree::Wildcard(vec![Tree::Specific(
+        "".to_owned(),
+        vec![
+            Tree::Parameter(":p".to_owned(), None, leaf(1)),
+            Tree::Specific("b".to_owned(), leaf(2)),
+            Tree::Specific("a".to_owned(), leaf(3)),
+        ],
+    )]);
+    let r = router::Router::from_tree(tree).unwrap();
+    assert!(matches!(
+        r.get(&route::new_route("/a").unwrap()),
+        Some((3, _))
+    ));
+    assert!(matches!(
+        r.get(&route::new_route("/b").unwrap()),
+        Some((2, _))
+    ));
+    assert!(matches!(
+        r.get(&route::new_route("/c").unwrap()),
+        Some((1, _))
+    ));
+}
+
+#[test]
+fn test_borrowed_params() {
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/:a/:b/:c/:d/:e/:f").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/x/:name/*").unwrap(), 2)
+        .unwrap();
+
+    //more parameters than fit inline
+    let request = route::new_route("/1/2/3/4/5/6").unwrap();
+    let (item, params) = r.get_borrowed(&request).unwrap();
+    assert_eq!(*item, 1);
+    assert_eq!(params.len(), 6);
+    for (name, value) in &[("a", "1"), ("c", "3"), ("f", "6")] {
+        assert_eq!(params.get_str(name), Some(*value));
+    }
+    let mut pairs: Vec<_> = params.iter().collect();
+    pairs.sort();
+    assert_eq!(pairs[0], ("a", "1"));
+    assert_eq!(pairs[5], ("f", "6"));
+    assert_eq!(params.query(), None);
+
+    //values are not decoded unless asked for
+    let request = route::new_route("/x/J%C3%BCrgen/a%20b/c").unwrap();
+    let (item, params) = r.get_borrowed(&request).unwrap();
+    assert_eq!(*item, 2);
+    assert_eq!(params.get_str("name"), Some("J%C3%BCrgen"));
+    assert_eq!(params.get_decoded("name").unwrap(), "Jürgen");
+    assert_eq!(params.get::<String>("name"), Ok("Jürgen".to_owned()));
+    assert_eq!(params.rest(), Some(("*", &["a%20b", "c"][..])));
+    assert_eq!(params.len(), 2);
+    assert!(!params.contains("*"));
+    assert!(matches!(
+        params.get::<u32>("missing"),
+        Err(crate::params::ParamError::Missing(_))
+    ));
+}
+
+#[test]
+fn test_route_str() {
+    let mut r: router::Router<u32> = router::new_router();
+    for (idx, pattern) in [
+        "/a/b",
+        "/a/:param",
+        "/files/*path",
+        "/any/*",
+        "/n/:id<u32>/x",
+        "/n/:name/x",
+    ]
+    .iter()
+    .enumerate()
+    {
+        r.add_route(&route::new_route(pattern).unwrap(), idx as u32)
+            .unwrap();
+    }
+
+    //the same results as splitting the path first
+    for path in &[
+        "/a/b",
+        "/a/c%20d?x=1&y",
+        "/a",
+        "/a/b/c",
+        "/files/x",
+        "/files/x%2Fy/z.txt#top",
+        "/any/thing/else",
+        "/n/7/x",
+        "/n/seven/x",
+        "/n/7",
+        "/unknown",
+    ] {
+        let expected = r
+            .get(&route::new_route(path).unwrap())
+            .map(|(x, params)| (*x, params));
+        let found = r.get_str(path).unwrap().map(|(x, params)| (*x, params));
+        assert_eq!(found, expected, "{}", path);
+        let routed = r.route_str(path).unwrap().map(|(x, params)| (*x, params));
+        assert_eq!(routed, expected, "{}", path);
+    }
+    match r.route_str("/files/a/b%20c?q=1") {
+        Ok(Some((x, params))) => {
+            *x += 10;
+            assert_eq!(params.get_str("path"), Some("a/b c"));
+            assert_eq!(params.query().get("q"), Some("1"));
+        }
+        other => panic!("Expected a match, got {:?}", other.map(|m| m.is_some())),
+    }
+    assert!(matches!(r.get_str("/files/a"), Ok(Some((12, _)))));
+
+    //malformed paths are rejected like new_route does
+    for path in &["", "a/b", "/a/", "/a//b", "/a/%zz", "/a/%ff"] {
+        assert_eq!(
+            r.get_str(path).err(),
+            route::new_route(path).err(),
+            "{}",
+            path
+        );
+        assert!(r.route_str(path).is_err());
+    }
+}
+
+#[test]
+fn test_mount() {
+    use crate::case::CaseSensitivity;
+
+    let mut admin: router::Router<u32> = router::new_router();
+    admin
+        .add_named_route("user", &route::new_route("/users/:id<u32>").unwrap(), 1)
+        .unwrap();
+    admin
+        .add_route(&route::new_route("/files/*path").unwrap(), 2)
+        .unwrap();
+    admin
+        .add_route(&route::new_route("/settings").unwrap(), 3)
+        .unwrap();
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/admin/:tenant/status").unwrap(), 0)
+        .unwrap();
+    r.mount(&route::new_route("/admin/:tenant").unwrap(), admin)
+        .unwrap();
+
+    match r.get(&route::new_route("/admin/acme/users/7").unwrap()) {
+        Some((x, params)) => {
+            assert_eq!(*x, 1);
+            assert_eq!(params.get_str("tenant"), Some("acme"));
+            assert_eq!(params.get::<u32>("id"), Ok(7));
+        }
+        None => panic!("Expected a match"),
+    }
+    match r.get(&route::new_route("/admin/acme/files/a/b").unwrap()) {
+        Some((x, params)) => {
+            assert_eq!(*x, 2);
+            assert_eq!(params.get_str("path"), Some("a/b"));
+        }
+        None => panic!("Expected a match"),
+    }
+    assert!(matches!(
+        r.get(&route::new_route("/admin/acme/status").unwrap()),
+        Some((0, _))
+    ));
+    //names are kept and build urls with the prefix
+    assert_eq!(
+        r.url_for("user", &[("tenant", "acme"), ("id", "7")]),
+        Ok("/admin/acme/users/7".to_owned())
+    );
+
+    //collisions name the full route and leave the router as it was
+    let mut other: router::Router<u32> = router::new_router();
+    other
+        .add_route(&route::new_route("/new").unwrap(), 4)
+        .unwrap();
+    other
+        .add_route(&route::new_route("/settings").unwrap(), 5)
+        .unwrap();
+    match r.mount(&route::new_route("/admin/:tenant").unwrap(), other) {
+        Err(router::MountError {
+            error: router::AddRouteError::MountCollision(path, e),
+            child,
+        }) => {
+            assert_eq!(path, "/admin/:tenant/settings");
+            assert!(
+                matches!(*e, router::AddRouteError::Collision(c) if c.kind == router::CollisionKind::RouteExists)
+            );
+            //the child router is given back as it was
+            assert_eq!(child.len(), 2);
+        }
+        other => panic!("Expected a collision, got {:?}", other),
+    }
+    assert!(r
+        .get(&route::new_route("/admin/acme/new").unwrap())
+        .is_none());
+    assert!(matches!(
+        r.get(&route::new_route("/admin/acme/settings").unwrap()),
+        Some((3, _))
+    ));
+
+    let mut named: router::Router<u32> = router::new_router();
+    named
+        .add_named_route("user", &route::new_route("/other").unwrap(), 6)
+        .unwrap();
+    assert!(matches!(
+        r.mount(&route::new_route("/x").unwrap(), named),
+        Err(router::MountError {
+            error: router::AddRouteError::NameExists(_),
+            ..
+        })
+    ));
+
+    //the prefix can not end with a named wildcard, the mounted routes would follow it
+    let mut files: router::Router<u32> = router::new_router();
+    files
+        .add_route(&route::new_route("/meta").unwrap(), 8)
+        .unwrap();
+    let files = match r.mount(&route::new_route("/files/*rest").unwrap(), files) {
+        Err(router::MountError {
+            error:
+                router::AddRouteError::MalformedPattern(route::MalformedRouteError::WildcardNotLast(7)),
+            child,
+        }) => child,
+        other => panic!("Expected a malformed prefix, got {:?}", other),
+    };
+    r.mount(&route::new_route("/files/*").unwrap(), *files)
+        .unwrap();
+    assert!(matches!(
+        r.get(&route::new_route("/files/x/meta").unwrap()),
+        Some((8, _))
+    ));
+
+    //parameters of the prefix and of the mounted routes need different names
+    let mut users: router::Router<u32> = router::new_router();
+    users
+        .add_route(&route::new_route("/u/:id").unwrap(), 9)
+        .unwrap();
+    match r.mount(&route::new_route("/t/:id").unwrap(), users) {
+        Err(router::MountError {
+            error: router::AddRouteError::MountCollision(path, e),
+            ..
+        }) => {
+            assert_eq!(path, "/t/:id/u/:id");
+            assert_eq!(
+                *e,
+                router::AddRouteError::MalformedPattern(
+                    route::MalformedRouteError::DuplicateParameterName(9)
+                )
+            );
+        }
+        other => panic!("Expected a duplicate name, got {:?}", other),
+    }
+    assert!(r.get(&route::new_route("/t/1/u/2").unwrap()).is_none());
+
+    //mounted routes keep the case sensitivity of their router
+    let mut legacy: router::Router<u32> = router::new_router();
+    legacy.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    legacy
+        .add_route(&route::new_route("/Report").unwrap(), 7)
+        .unwrap();
+    r.mount(&route::new_route("/legacy").unwrap(), legacy)
+        .unwrap();
+    assert!(matches!(
+        r.get(&route::new_route("/legacy/REPORT").unwrap()),
+        Some((7, _))
+    ));
+    //it applies to the whole route, including the prefix
+    assert!(matches!(
+        r.get(&route::new_route("/LEGACY/report").unwrap()),
+        Some((7, _))
+    ));
+    assert!(r
+        .get(&route::new_route("/admin/acme/SETTINGS").unwrap())
+        .is_none());
+
+    //a mount that fails leaves no routes behind, even if they went into a part with another case
+    let mut r: router::Router<u32> = router::new_router();
+    r.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    r.add_route(&route::new_route("/Admin/x").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/admin/z").unwrap(), 2)
+        .unwrap();
+    let mut child: router::Router<u32> = router::new_router();
+    child.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+    child
+        .add_route(&route::new_route("/y").unwrap(), 3)
+        .unwrap();
+    child
+        .add_route(&route::new_route("/z").unwrap(), 4)
+        .unwrap();
+    let child = match r.mount(&route::new_route("/admin").unwrap(), child) {
+        Err(router::MountError { child, .. }) => child,
+        Ok(()) => panic!("Expected a collision"),
+    };
+    assert_eq!(r.len(), 2);
+    assert!(!r.contains_route(&route::new_route("/Admin/y").unwrap()));
+    assert!(r.get(&route::new_route("/admin/y").unwrap()).is_none());
+    assert_eq!(child.len(), 2);
+}
+
+#[test]
+fn test_iteration() {
+    let mut r: router::Router<u32> = router::new_router();
+    assert!(r.is_empty());
+    assert_eq!(r.iter().count(), 0);
+    let patterns = [
+        "/users",
+        "/users/:id<u32>",
+        "/users/:name",
+        "/files/*path",
+        "/any/*",
+        "/a/*/b",
+        "/a%20b",
+    ];
+    for (idx, pattern) in patterns.iter().enumerate() {
+        r.add_route(&route::new_route(pattern).unwrap(), idx as u32)
+            .unwrap();
+    }
+    assert_eq!(r.len(), patterns.len());
+    assert!(!r.is_empty());
+
+    let mut found: Vec<_> = r
+        .iter()
+        .map(|(pattern, item)| (pattern.to_string(), *item))
+        .collect();
+    found.sort_by_key(|(_, item)| *item);
+    let expected: Vec<_> = patterns
+        .iter()
+        .enumerate()
+        .map(|(idx, pattern)| (pattern.to_string(), idx as u32))
+        .collect();
+    assert_eq!(found, expected);
+
+    for (pattern, item) in r.iter_mut() {
+        if pattern.path.len() == 2 {
+            *item += 100;
+        }
+    }
+    assert!(matches!(
+        r.get(&route::new_route("/users").unwrap()),
+        Some((100, _))
+    ));
+
+    assert!(r.contains_route(&route::new_route("/users/:id<u32>").unwrap()));
+    assert!(r.contains_route(&route::new_route("/files/*path").unwrap()));
+    assert!(r.contains_route(&route::new_route("/a b").unwrap()));
+    assert!(!r.contains_route(&route::new_route("/users/7").unwrap()));
+    assert!(!r.contains_route(&route::new_route("/users/:id").unwrap()));
+    assert!(!r.contains_route(&route::new_route("/a").unwrap()));
+
+    r.remove_route(&route::new_route("/users").unwrap());
+    assert_eq!(r.len(), patterns.len() - 1);
+    assert!(!r.contains_route(&route::new_route("/users").unwrap()));
+}
+
+#[test]
+fn test_render_tree() {
+    use crate::case::CaseSensitivity;
+
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_route("/users/:id<u32>").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_route("/users/me").unwrap(), 2)
+        .unwrap();
+    r.add_route_with_case(
+        &route::new_route("/files/*path").unwrap(),
+        3,
+        CaseSensitivity::AsciiInsensitive,
+    )
+    .unwrap();
+    r.add_route(&route::new_route("/\"quoted\"").unwrap(), 4)
+        .unwrap();
+
+    let text = "*
+└── \"\"
+    ├── \"quoted\"
+    │   └── (leaf)
+    ├── files
+    │   └── *
+    │       └── (leaf, catch-all: path) [AsciiInsensitive]
+    └── users
+        ├── me
+        │   └── (leaf)
+        └── :id<u32>
+            └── (leaf)";
+    assert_eq!(r.to_string(), text);
+    assert_eq!(r.tree().to_string(), text);
+    let debug = format!("{:?}", r);
+    assert!(debug.contains("└── (leaf, catch-all: path) [AsciiInsensitive] = 3"));
+    assert!(debug.contains("│   └── (leaf) = 2"));
+
+    let dot = r.to_dot();
+    assert!(dot.starts_with("digraph router {\n    n0 [label=\"*\"];\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("[label=\"\\\"quoted\\\"\"];"));
+    assert!(dot.contains("[label=\"(leaf)\", shape=box];"));
+    assert!(dot.contains("    n0 -> n1;\n"));
+    //one edge for every node but the root
+    assert_eq!(
+        dot.matches("->").count(),
+        dot.matches("[label=").count() - 1
+    );
+    assert!(r
+        .to_dot_with_items()
+        .contains("[label=\"(leaf) = 2\", shape=box];"));
+}
+
+#[test]
+fn test_route_all() {
+    let mut r: router::Router<&str> = router::new_router();
+    //added in the opposite order of their specificity
+    r.add_route(&route::new_route("/api/*rest").unwrap(), "api")
+        .unwrap();
+    r.add_route(&route::new_route("/api/:version/*").unwrap(), "version")
+        .unwrap();
+    r.add_route(&route::new_route("/api/v1/users").unwrap(), "users")
+        .unwrap();
+    r.add_route(&route::new_route("/api/v1/:name").unwrap(), "name")
+        .unwrap();
+    r.add_route(&route::new_route("/other/*").unwrap(), "other")
+        .unwrap();
+
+    let request = route::new_route("/api/v1/users?page=2").unwrap();
+    let found = r.get_all(&request);
+    let items: Vec<_> = found.iter().map(|(item, _)| **item).collect();
+    assert_eq!(items, ["users", "name", "version", "api"]);
+    //the first match is the one get returns
+    assert_eq!(*r.get(&request).unwrap().0, "users");
+
+    //every match has its own parameters
+    assert!(found[0].1.is_empty());
+    assert_eq!(found[1].1.get_str("name"), Some("users"));
+    assert_eq!(found[2].1.get_str("version"), Some("v1"));
+    assert_eq!(found[2].1.get_str("*"), Some("users"));
+    assert_eq!(found[3].1.get_str("rest"), Some("v1/users"));
+    assert_eq!(found[3].1.get_str("version"), None);
+    assert_eq!(found[3].1.query().get("page"), Some("2"));
+
+    let request = route::new_route("/api/v2/users/1").unwrap();
+    for (item, _) in r.route_all(&request) {
+        *item = "changed";
+    }
+    assert_eq!(
+        r.get_all(&request)
+            .into_iter()
+            .map(|(item, _)| *item)
+            .collect::<Vec<_>>(),
+        ["changed", "changed"]
+    );
+    assert_eq!(
+        *r.get(&route::new_route("/api/v1/users").unwrap())
+            .unwrap()
+            .0,
+        "users"
+    );
+
+    assert!(r.get_all(&route::new_route("/missing").unwrap()).is_empty());
+    assert!(r.route_all(&route::new_route("/api").unwrap()).is_empty());
+}
+
+/// A small linear congruential generator, so the shuffles are the same in every run.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for idx in (1..items.len()).rev() {
+            let other = (self.next() % (idx as u64 + 1)) as usize;
+            items.swap(idx, other);
+        }
+    }
+}
+
+#[test]
+fn test_specificity() {
+    use crate::case::CaseSensitivity;
+    use crate::specificity::MatchedPart::*;
+
+    let patterns = [
+        "/api/v1/users",
+        "/api/v1/:name",
+        "/api/:version/users",
+        "/api/:version<u32>/users",
+        "/api/:version<[a-z0-9]+>/users",
+        "/api/:version/*",
+        "/api/*/users",
+        "/api/*rest",
+        "/:section/*",
+        "/*",
+    ];
+    let requests = [
+        "/api/v1/users",
+        "/API/V1/Users",
+        "/api/v2/users",
+        "/api/2/users",
+        "/api/v-2/users",
+        "/api/v-2/other",
+        "/api/v1/other",
+        "/api/v1/users/1",
+        "/other/x",
+        "/other",
+    ];
+    let build = |order: &[usize]| {
+        let mut r: router::Router<usize> = router::new_router();
+        r.set_case_sensitivity(CaseSensitivity::AsciiInsensitive);
+        for idx in order {
+            r.add_route(&route::new_route(patterns[*idx]).unwrap(), *idx)
+                .unwrap();
+        }
+        r
+    };
+    //the items and scores of all matches for every request
+    let lookup = |r: &router::Router<usize>| -> Vec<Vec<(usize, Vec<_>)>> {
+        requests
+            .iter()
+            .map(|request| {
+                r.get_all(&route::new_route(request).unwrap())
+                    .into_iter()
+                    .map(|(item, params)| (*item, params.specificity().parts().to_vec()))
+                    .collect()
+            })
+            .collect()
+    };
+
+    let mut order: Vec<usize> = (0..patterns.len()).collect();
+    let r = build(&order);
+    let expected = lookup(&r);
+    let first = |request: &str| {
+        let (item, params) = r.get(&route::new_route(request).unwrap()).unwrap();
+        (patterns[*item], params.specificity().clone())
+    };
+    let (pattern, score) = first("/api/v1/users");
+    assert_eq!(pattern, "/api/v1/users");
+    assert_eq!(score.parts(), [Specific; 4]);
+    assert_eq!(score.static_prefix(), 4);
+    let (pattern, score) = first("/API/V1/Users");
+    assert_eq!(pattern, "/api/v1/users");
+    assert_eq!(
+        score.parts(),
+        [
+            Specific,
+            SpecificIgnoringCase,
+            SpecificIgnoringCase,
+            SpecificIgnoringCase
+        ]
+    );
+    let (pattern, score) = first("/api/2/users");
+    assert_eq!(pattern, "/api/:version<u32>/users");
+    assert_eq!(
+        score.parts(),
+        [Specific, Specific, ConstrainedParameter, Specific]
+    );
+    assert_eq!(score.static_prefix(), 2);
+    assert_eq!(first("/api/v-2/users").0, "/api/:version/users");
+    let (pattern, score) = first("/api/v-2/other");
+    assert_eq!(pattern, "/api/:version/*");
+    assert_eq!(
+        score.parts(),
+        [Specific, Specific, Parameter, Wildcard, CatchAll]
+    );
+    assert_eq!(first("/api/v1/users/1").0, "/api/:version/*");
+    assert_eq!(first("/other").0, "/*");
+
+    for request in &expected {
+        let scores: Vec<_> = request.iter().map(|(_, score)| score).collect();
+        //no match is more specific than the one before
+        assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+    //both constraints accept "2", they score the same and the type is tried before the pattern
+    let both = &expected[3];
+    assert_eq!(patterns[both[0].0], "/api/:version<u32>/users");
+    assert_eq!(patterns[both[1].0], "/api/:version<[a-z0-9]+>/users");
+    assert_eq!(both[0].1, both[1].1);
+
+    //the order the routes are added in makes no difference
+    let mut rng = Lcg(0x2545_f491_4f6c_dd1d);
+    for _ in 0..200 {
+        rng.shuffle(&mut order);
+        let r = build(&order);
+        assert_eq!(lookup(&r), expected, "added in the order {:?}", order);
+    }
+}
+
+#[test]
+fn test_request_paths_with_pattern_characters() {
+    let mut r: router::Router<u32> = router::new_router();
+    r.add_route(&route::new_pattern("/static/*file").unwrap(), 1)
+        .unwrap();
+    r.add_route(&route::new_pattern("/a/:x").unwrap(), 2)
+        .unwrap();
+    r.add_route(&route::new_pattern("/b/:x/c").unwrap(), 3)
+        .unwrap();
+
+    let (item, params) = r.get(&route::new_route("/static/*.js").unwrap()).unwrap();
+    assert_eq!((*item, params.get_str("file")), (1, Some("*.js")));
+    let (item, params) = r.get(&route::new_route("/a/:x-y").unwrap()).unwrap();
+    assert_eq!((*item, params.get_str("x")), (2, Some(":x-y")));
+    let (item, params) = r.get_str("/b/*name/c").unwrap().unwrap();
+    assert_eq!((*item, params.get_str("x")), (3, Some("*name")));
+    let (item, params) = r.route_str("/static/:a/*b").unwrap().unwrap();
+    assert_eq!((*item, params.get_str("file")), (1, Some(":a/*b")));
+    match r.get_path("/a/:x<u32").unwrap() {
+        router::PathMatch::Found(item, params) => {
+            assert_eq!((*item, params.get_str("x")), (2, Some(":x<u32")))
+        }
+        other => panic!("Expected a match, got {:?}", other),
+    }
+    //the request is not a pattern, so "*" is not a wildcard there
+    assert!(r.get(&route::new_route("/b/x/*").unwrap()).is_none());
 }
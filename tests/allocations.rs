@@ -0,0 +1,71 @@
+//! Checks that Router::get_borrowed does not allocate. This has its own test binary because the
+//! counting allocator is global.
+
+use router::route::new_route;
+use router::router::new_router;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_borrowed_lookup_does_not_allocate() {
+    let mut r = new_router();
+    r.add_route(&new_route("/users/:id<u64>/posts/:slug").unwrap(), 1)
+        .unwrap();
+    r.add_route(&new_route("/users/:name<[a-z]+>").unwrap(), 2)
+        .unwrap();
+    r.add_route(&new_route("/static/*path").unwrap(), 3)
+        .unwrap();
+    for i in 0..100 {
+        r.add_route(&new_route(&format!("/section{}/:id", i)).unwrap(), 4)
+            .unwrap();
+    }
+    let posts = new_route("/users/42/posts/hello-world?page=2").unwrap();
+    let user = new_route("/users/bob").unwrap();
+    let file = new_route("/static/css/site.css").unwrap();
+    let section = new_route("/section57/x").unwrap();
+    let missing = new_route("/users/42/comments").unwrap();
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    let (item, params) = r.get_borrowed(&posts).unwrap();
+    assert_eq!(*item, 1);
+    assert_eq!(params.get_str("id"), Some("42"));
+    assert_eq!(params.get_str(":slug"), Some("hello-world"));
+    assert_eq!(params.get::<u64>("id"), Ok(42));
+    assert_eq!(params.query(), Some("page=2"));
+    let (item, params) = r.get_borrowed(&user).unwrap();
+    assert_eq!(*item, 2);
+    assert_eq!(params.get_str("name"), Some("bob"));
+    let (item, params) = r.get_borrowed(&file).unwrap();
+    assert_eq!(*item, 3);
+    assert_eq!(params.rest(), Some(("path", &["css", "site.css"][..])));
+    assert!(matches!(r.get_borrowed(&section), Some((4, _))));
+    assert!(r.get_borrowed(&missing).is_none());
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), before);
+
+    //the owned lookup allocates for its parameters
+    assert!(r.get(&posts).is_some());
+    assert!(ALLOCATIONS.load(Ordering::SeqCst) > before);
+}